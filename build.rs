@@ -0,0 +1,21 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Lists the embedded migrations as `(version, name)`, the version being the directory name up to the first `_`
+/// without dashes as Diesel records it, so the server can tell which are pending without running them.
+fn main() {
+  println!("cargo:rerun-if-changed=migrations");
+
+  let mut migrations: Vec<(String, String)> = fs::read_dir("migrations")
+    .expect("Unable to read the migrations directory")
+    .filter_map(Result::ok)
+    .filter(|entry| entry.path().join("up.sql").is_file())
+    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+    .map(|name| (name.split('_').next().unwrap_or_default().replace('-', ""), name))
+    .collect();
+  migrations.sort();
+
+  let out = Path::new(&env::var("OUT_DIR").unwrap()).join("migrations.rs");
+  fs::write(out, format!("const MIGRATIONS: &[(&str, &str)] = &{:?};\n", migrations)).unwrap();
+}
@@ -0,0 +1,16 @@
+CREATE TABLE authorization_rules (
+  id uuid NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+  subject_kind VARCHAR NOT NULL CHECK (subject_kind IN ('User', 'Group')),
+  subject_name VARCHAR NOT NULL,
+  verbs TEXT[] NOT NULL DEFAULT '{}',
+  api_groups TEXT[] NOT NULL DEFAULT '{}',
+  resources TEXT[] NOT NULL DEFAULT '{}',
+  namespaces TEXT[] NOT NULL DEFAULT '{}',
+  non_resource_urls TEXT[] NOT NULL DEFAULT '{}',
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_authorization_rules_subject ON authorization_rules (subject_kind, subject_name);
+
+SELECT diesel_manage_updated_at('authorization_rules');
@@ -0,0 +1,5 @@
+DROP INDEX idx_tokens_user_id;
+
+ALTER TABLE tokens
+  DROP COLUMN revoked_reason,
+  DROP COLUMN revoked_at;
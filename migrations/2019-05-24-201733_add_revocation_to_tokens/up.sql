@@ -0,0 +1,5 @@
+ALTER TABLE tokens
+  ADD COLUMN revoked_at TIMESTAMP WITHOUT TIME ZONE,
+  ADD COLUMN revoked_reason VARCHAR;
+
+CREATE INDEX idx_tokens_user_id ON tokens (user_id);
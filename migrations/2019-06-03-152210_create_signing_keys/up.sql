@@ -0,0 +1,17 @@
+CREATE TABLE signing_keys (
+  id uuid NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+  kid VARCHAR NOT NULL UNIQUE,
+  algorithm VARCHAR NOT NULL,
+  public_key TEXT NOT NULL,
+  private_key TEXT,
+  active BOOLEAN NOT NULL DEFAULT FALSE,
+  retire_at TIMESTAMP WITHOUT TIME ZONE,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+  CHECK (NOT active OR private_key IS NOT NULL)
+);
+
+-- Only one key may sign at a time
+CREATE UNIQUE INDEX idx_signing_keys_active ON signing_keys (active) WHERE active;
+
+SELECT diesel_manage_updated_at('signing_keys');
@@ -0,0 +1,5 @@
+ALTER TABLE tokens DROP CONSTRAINT fk_tokens_user_id;
+
+DROP TABLE user_groups;
+DROP TABLE groups;
+DROP TABLE users;
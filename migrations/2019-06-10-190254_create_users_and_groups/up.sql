@@ -0,0 +1,31 @@
+CREATE TABLE users (
+  id uuid NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+  username VARCHAR NOT NULL UNIQUE,
+  extra jsonb NOT NULL DEFAULT '{}'::jsonb,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+SELECT diesel_manage_updated_at('users');
+
+CREATE TABLE groups (
+  id uuid NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+  name VARCHAR NOT NULL UNIQUE,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+SELECT diesel_manage_updated_at('groups');
+
+CREATE TABLE user_groups (
+  user_id uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  group_id uuid NOT NULL REFERENCES groups (id) ON DELETE CASCADE,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (user_id, group_id)
+);
+
+CREATE INDEX idx_user_groups_group_id ON user_groups (group_id);
+
+-- Existing tokens may predate the users table, so only new rows are checked
+ALTER TABLE tokens
+  ADD CONSTRAINT fk_tokens_user_id FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE NOT VALID;
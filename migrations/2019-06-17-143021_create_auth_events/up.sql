@@ -0,0 +1,14 @@
+-- No foreign keys, the audit trail has to outlive the users and tokens it mentions
+CREATE TABLE auth_events (
+  id uuid NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+  jti uuid,
+  user_id uuid,
+  decision VARCHAR NOT NULL,
+  reason VARCHAR,
+  audiences TEXT[] NOT NULL DEFAULT '{}',
+  remote_addr VARCHAR,
+  created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_auth_events_created_at ON auth_events (created_at);
+CREATE INDEX idx_auth_events_user_id_created_at ON auth_events (user_id, created_at);
@@ -0,0 +1,163 @@
+use serde_json::{Map, Value};
+
+use crate::settings::ClaimMapping;
+use crate::kubernetes::authentication::v1::UserInfo;
+
+/// Applies a claim mapping to the claims of verified tokens.
+#[derive(Clone, Debug)]
+pub struct ClaimMapper {
+  username_claim: Option<String>,
+  username_prefix: String,
+  groups_claims: Vec<String>,
+  groups_prefix: String,
+  extra_claims: Vec<String>
+}
+
+impl ClaimMapper {
+  /// Creates a mapper, falling back to the authenticator's defaults for what the mapping leaves out.
+  ///
+  /// # Arguments
+  /// * `mapping`         - Mapping rules from settings.
+  /// * `username_claim`  - Claim holding the username when the mapping names none, `None` keeps the username the
+  ///                       authenticator found.
+  /// * `username_prefix` - Prefix used when the mapping sets none.
+  pub fn new(mapping: &ClaimMapping, username_claim: Option<&str>, username_prefix: &str) -> Self {
+    let username_prefix = match mapping.username_prefix {
+      Some(ref prefix) if prefix == "-" => String::new(),
+      Some(ref prefix)                  => prefix.to_owned(),
+      None                              => username_prefix.to_owned()
+    };
+
+    ClaimMapper {
+      username_claim: mapping.username_claim.to_owned().or_else(|| username_claim.map(str::to_owned)),
+      username_prefix,
+      groups_claims: mapping.groups_claims.to_owned(),
+      groups_prefix: mapping.groups_prefix.to_owned().unwrap_or_default(),
+      extra_claims: mapping.extra_claims.to_owned()
+    }
+  }
+
+  /// Claim the username is taken from, if any.
+  pub fn username_claim(&self) -> Option<&str> {
+    self.username_claim.as_ref().map(String::as_str)
+  }
+
+  /// Updates a user from the claims of their token, or returns why the claims cannot be used.
+  /// Groups taken from claims are added to the user's own, `extra` claims replace attributes of the same name.
+  ///
+  /// # Arguments
+  /// * `claims` - Verified claims of the token.
+  /// * `user`   - User to update.
+  pub fn apply(&self, claims: &Map<String, Value>, user: &mut UserInfo) -> Result<(), &'static str> {
+    if let Some(ref claim) = self.username_claim {
+      match claims.get(claim) {
+        Some(Value::String(username)) if !username.is_empty() => user.username = Some(username.to_owned()),
+        _ => return Err("missing_username")
+      }
+    }
+
+    if let Some(ref mut username) = user.username {
+      username.insert_str(0, &self.username_prefix);
+    }
+
+    let mut found = Vec::new();
+    for claim in &self.groups_claims {
+      match claims.get(claim) {
+        None                       => (),
+        Some(Value::String(group)) => found.push(group.to_owned()),
+        Some(Value::Array(groups)) => {
+          for group in groups {
+            found.push(group.as_str().ok_or("invalid_groups")?.to_owned());
+          }
+        },
+        Some(_) => return Err("invalid_groups")
+      }
+    }
+
+    if !found.is_empty() {
+      let groups = user.groups.get_or_insert_with(Vec::new);
+      for group in found {
+        let group = format!("{}{}", self.groups_prefix, group);
+        if !groups.contains(&group) {
+          groups.push(group);
+        }
+      }
+    }
+
+    for claim in &self.extra_claims {
+      let values = match claims.get(claim) {
+        None | Some(Value::Null)   => continue,
+        Some(Value::Array(values)) => values.iter().map(text).collect(),
+        Some(value)                => vec![text(value)]
+      };
+      user.extra.get_or_insert_with(Default::default).insert(claim.to_owned(), values);
+    }
+
+    Ok(())
+  }
+}
+
+/// A claim value as an `extra` string, strings are taken as is and anything else as JSON.
+fn text(value: &Value) -> String {
+  match value {
+    Value::String(value) => value.to_owned(),
+    value                => value.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use serde_json::json;
+  use super::*;
+
+  fn claims(value: Value) -> Map<String, Value> {
+    match value {
+      Value::Object(claims) => claims,
+      _                     => unreachable!()
+    }
+  }
+
+  speculate! {
+    it "maps claims with the configured rules" {
+      let mapping = ClaimMapping {
+        username_claim: Some("email".into()),
+        username_prefix: Some("tenant-a:".into()),
+        groups_claims: vec!["scopes".into(), "team".into()],
+        groups_prefix: Some("scope:".into()),
+        extra_claims: vec!["tenant".into(), "level".into(), "missing".into()]
+      };
+      let claims = claims(json!({ "email": "kitty@example.com", "scopes": ["read", "write"], "team": "ops", "tenant": "a", "level": 3 }));
+
+      let mut user = UserInfo { username: Some("kitty".into()), groups: Some(vec!["developers".into()]), ..Default::default() };
+      ClaimMapper::new(&mapping, None, "").apply(&claims, &mut user).unwrap();
+
+      assert_eq!(user.username, Some("tenant-a:kitty@example.com".into()));
+      assert_eq!(user.groups, Some(vec!["developers".into(), "scope:read".into(), "scope:write".into(), "scope:ops".into()]));
+
+      let extra = user.extra.unwrap();
+      assert_eq!(extra.get("tenant"), Some(&vec!["a".to_owned()]));
+      assert_eq!(extra.get("level"), Some(&vec!["3".to_owned()]));
+      assert_eq!(extra.get("missing"), None);
+    }
+
+    it "keeps the authenticator's defaults unless overridden" {
+      let mut user = UserInfo::default();
+      ClaimMapper::new(&ClaimMapping::default(), Some("sub"), "issuer#").apply(&claims(json!({ "sub": "kitty" })), &mut user).unwrap();
+      assert_eq!(user, UserInfo { username: Some("issuer#kitty".into()), ..Default::default() });
+
+      let mapping  = ClaimMapping { username_prefix: Some("-".into()), ..Default::default() };
+      let mut user = UserInfo::default();
+      ClaimMapper::new(&mapping, Some("sub"), "issuer#").apply(&claims(json!({ "sub": "kitty" })), &mut user).unwrap();
+      assert_eq!(user.username, Some("kitty".into()));
+    }
+
+    it "refuses unusable claims" {
+      let mapping = ClaimMapping { username_claim: Some("email".into()), groups_claims: vec!["groups".into()], ..Default::default() };
+      let mapper  = ClaimMapper::new(&mapping, None, "");
+
+      assert_eq!(mapper.apply(&claims(json!({ "sub": "kitty" })), &mut UserInfo::default()), Err("missing_username"));
+      assert_eq!(mapper.apply(&claims(json!({ "email": "kitty@example.com", "groups": [1] })), &mut UserInfo::default()), Err("invalid_groups"));
+    }
+  }
+}
@@ -0,0 +1,93 @@
+use jsonwebtoken::decode_header;
+
+use crate::token;
+use crate::db::Database;
+use crate::models::{Token, User};
+use crate::settings::ClaimMapping;
+use crate::signing::KeyRing;
+use crate::server::HttpError;
+use crate::kubernetes::authentication::v1::TokenReviewSpec;
+use super::{unverified_issuer, Authenticator, ClaimMapper, Outcome, TokenDetails};
+
+/// Authenticates JWTs issued by this service against the key ring and the `tokens` table.
+pub struct JwtAuthenticator {
+  keys: KeyRing,
+  database: Database,
+  mapper: ClaimMapper
+}
+
+impl JwtAuthenticator {
+  /// Creates the authenticator.
+  ///
+  /// # Arguments
+  /// * `keys`     - Key ring verifying tokens.
+  /// * `database` - Database holding issued tokens and users.
+  /// * `mapping`  - Rules applied to the token's claims on top of the stored user, who keeps their username by default.
+  pub fn new(keys: KeyRing, database: Database, mapping: &ClaimMapping) -> Self {
+    JwtAuthenticator { keys, database, mapper: ClaimMapper::new(mapping, None, "") }
+  }
+
+  /// Whether a token looks like one of ours: a JWT naming one of our keys (or none) and our issuer (or none).
+  fn recognizes(&self, token: &str) -> bool {
+    let header = match decode_header(token) {
+      Ok(header) => header,
+      Err(_)     => return false
+    };
+
+    let known_key = header.kid.map_or(true, |kid| self.keys.find(&kid).is_some());
+    known_key && unverified_issuer(token).map_or(true, |issuer| issuer == self.keys.issuer)
+  }
+}
+
+impl Authenticator for JwtAuthenticator {
+  fn name(&self) -> &str {
+    "jwt"
+  }
+
+  fn authenticate(&self, spec: &TokenReviewSpec) -> Result<Outcome, HttpError> {
+    if !self.recognizes(&spec.token) {
+      return Ok(Outcome::NotMine);
+    }
+
+    let mut details = TokenDetails::default();
+    let claims = match token::decode(&self.keys, &spec.token) {
+      Ok(claims) => claims,
+      Err(_)     => return Ok(Outcome::Denied("invalid_token", details))
+    };
+    details.jti = Some(claims.jti);
+
+    // Unknown, expired and revoked tokens all look the same from here
+    let conn   = self.database.pool.get()?;
+    let stored = match Token::find_active(claims.jti, &conn) {
+      Ok(stored)               => stored,
+      Err(HttpError::NotFound) => return Ok(Outcome::Denied("inactive_token", details)),
+      Err(e)                   => return Err(e)
+    };
+    details.user_id    = Some(stored.user_id);
+    details.expires_at = Some(stored.expires_at);
+
+    // Deny tokens that were not issued for any of the requested audiences
+    let audiences = match stored.claims.matching_audiences(spec.audiences.as_ref().map(Vec::as_slice).unwrap_or(&[])) {
+      Some(audiences) => audiences,
+      None            => return Ok(Outcome::Denied("audience_mismatch", details))
+    };
+
+    let user = match User::find(stored.user_id, &conn) {
+      Ok(user)                 => user,
+      Err(HttpError::NotFound) => return Ok(Outcome::Denied("unknown_user", details)),
+      Err(e)                   => return Err(e)
+    };
+    let groups = user.group_names(&conn)?;
+
+    let mut user_info = user.user_info(groups, &stored.claims);
+    let claims = match serde_json::to_value(&stored.claims) {
+      Ok(serde_json::Value::Object(claims)) => claims,
+      _ => return Err(HttpError::InternalServerError)
+    };
+    if let Err(reason) = self.mapper.apply(&claims, &mut user_info) {
+      return Ok(Outcome::Denied(reason, details));
+    }
+
+    Ok(Outcome::Authenticated(user_info, audiences, details))
+  }
+}
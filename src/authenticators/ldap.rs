@@ -0,0 +1,344 @@
+use failure::{Fallible, format_err};
+use ldap3::{ldap_escape, LdapConn, LdapConnSettings, Scope, SearchEntry};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::settings;
+use crate::server::Metrics;
+use crate::kubernetes::authentication::v1::UserInfo;
+
+/// Where group memberships are looked up, so tests can stand in for a real directory.
+pub trait Directory: Send + Sync {
+  /// Groups of a user, empty when the user is not in the directory.
+  ///
+  /// # Arguments
+  /// * `username` - User to look up.
+  fn groups(&self, username: &str) -> Fallible<Vec<String>>;
+}
+
+/// Searches an LDAP directory for the user's entry and reads its group attribute.
+/// A connection is opened per lookup, lookups are rare thanks to the cache in front.
+/// Connecting, binding and searching share a single deadline.
+pub struct LdapDirectory {
+  url: String,
+  bind: Option<(String, String)>,
+  base_dn: String,
+  user_filter: String,
+  group_attribute: String,
+  timeout: Duration
+}
+
+impl LdapDirectory {
+  /// Creates the directory using settings, without connecting to it.
+  ///
+  /// # Arguments
+  /// * `ldap` - LDAP settings to use.
+  pub fn from_settings(ldap: &settings::Ldap) -> Fallible<Self> {
+    if !ldap.user_filter.contains("{username}") {
+      return Err(format_err!("ldap.user_filter must contain {{username}}"));
+    }
+
+    Ok(LdapDirectory {
+      url: ldap.url.to_owned(),
+      bind: ldap.bind_dn.as_ref().map(|dn| (dn.to_owned(), ldap.bind_password.to_owned().unwrap_or_default())),
+      base_dn: ldap.base_dn.to_owned(),
+      user_filter: ldap.user_filter.to_owned(),
+      group_attribute: ldap.group_attribute.to_owned(),
+      timeout: Duration::from_secs(ldap.timeout)
+    })
+  }
+}
+
+impl Directory for LdapDirectory {
+  fn groups(&self, username: &str) -> Fallible<Vec<String>> {
+    let deadline = Instant::now() + self.timeout;
+    let ldap     = LdapConn::with_settings(LdapConnSettings::new().set_conn_timeout(remaining(deadline)?), &self.url)?;
+
+    if let Some((ref dn, ref password)) = self.bind {
+      ldap.with_timeout(remaining(deadline)?).simple_bind(dn, password)?.success()?;
+    }
+
+    let filter = self.user_filter.replace("{username}", &ldap_escape(username));
+    let (entries, _) = ldap.with_timeout(remaining(deadline)?)
+      .search(&self.base_dn, Scope::Subtree, &filter, vec![self.group_attribute.as_str()])?
+      .success()?;
+    let _ = ldap.unbind();
+
+    if entries.len() > 1 {
+      return Err(format_err!("{} entries match the filter {}", entries.len(), filter));
+    }
+
+    // Attribute names are case insensitive, servers answer with their own spelling
+    Ok(entries.into_iter()
+      .map(SearchEntry::construct)
+      .flat_map(|entry| entry.attrs.into_iter())
+      .filter(|(attribute, _)| attribute.eq_ignore_ascii_case(&self.group_attribute))
+      .flat_map(|(_, values)| values.into_iter())
+      .map(|value| group_name(&value))
+      .collect())
+  }
+}
+
+/// Time left until `deadline`, an error once it has passed.
+fn remaining(deadline: Instant) -> Fallible<Duration> {
+  let now = Instant::now();
+  if now >= deadline {
+    return Err(format_err!("timed out"));
+  }
+  Ok(deadline - now)
+}
+
+struct Entry {
+  groups: Vec<String>,
+  expires_at: Instant
+}
+
+/// Adds the groups a directory knows a user by to authenticated users.
+/// Lookups are cached, users missing from the directory included. A directory that cannot be reached costs the user
+/// their directory groups, and is not asked again for `retry_after` so an outage does not slow every review down.
+pub struct GroupLookup {
+  directory: Box<dyn Directory>,
+  prefix: String,
+  ttl: Duration,
+  capacity: usize,
+  retry_after: Duration,
+  entries: Mutex<HashMap<String, Entry>>,
+  unavailable_until: Mutex<Option<Instant>>,
+  metrics: Metrics
+}
+
+impl GroupLookup {
+  /// Creates the lookup using settings.
+  ///
+  /// # Arguments
+  /// * `ldap`    - LDAP settings to use.
+  /// * `metrics` - Metrics to count lookups in.
+  pub fn from_settings(ldap: &settings::Ldap, metrics: &Metrics) -> Fallible<Self> {
+    let directory = LdapDirectory::from_settings(ldap)?;
+    info!("Looking up groups in {} under {}", ldap.url, ldap.base_dn);
+
+    Ok(Self::new(
+      Box::new(directory),
+      ldap.groups_prefix.to_owned().unwrap_or_default(),
+      Duration::from_secs(ldap.cache_ttl),
+      ldap.cache_capacity,
+      Duration::from_secs(ldap.retry_after),
+      metrics.clone()
+    ))
+  }
+
+  /// Creates the lookup.
+  ///
+  /// # Arguments
+  /// * `directory`   - Directory holding group memberships.
+  /// * `prefix`      - Prepended to every group found.
+  /// * `ttl`         - How long the groups of a user are kept.
+  /// * `capacity`    - Most users whose groups are kept at once, `0` disables the cache.
+  /// * `retry_after` - How long the directory is left alone after a failed lookup.
+  /// * `metrics`     - Metrics to count lookups in.
+  pub fn new(directory: Box<dyn Directory>, prefix: String, ttl: Duration, capacity: usize, retry_after: Duration, metrics: Metrics) -> Self {
+    GroupLookup {
+      directory,
+      prefix,
+      ttl,
+      capacity,
+      retry_after,
+      entries: Mutex::new(HashMap::new()),
+      unavailable_until: Mutex::new(None),
+      metrics
+    }
+  }
+
+  /// Adds the user's directory groups to the ones they already have.
+  /// Returns `false` when the groups could not be looked up, so the user is missing their directory groups.
+  ///
+  /// # Arguments
+  /// * `user` - Authenticated user.
+  pub fn enrich(&self, user: &mut UserInfo) -> bool {
+    let found = match user.username {
+      Some(ref username) => self.groups(username),
+      None               => return true
+    };
+    let found = match found {
+      Some(found) => found,
+      None        => return false
+    };
+
+    let groups = user.groups.get_or_insert_with(Vec::new);
+    for group in found {
+      let group = format!("{}{}", self.prefix, group);
+      if !groups.contains(&group) {
+        groups.push(group);
+      }
+    }
+
+    if groups.is_empty() {
+      user.groups = None;
+    }
+    true
+  }
+
+  /// Groups of a user, from the cache while fresh, `None` when the directory failed or is being left alone.
+  fn groups(&self, username: &str) -> Option<Vec<String>> {
+    if let Some(entry) = self.lock().get(username).filter(|entry| entry.expires_at > Instant::now()) {
+      self.metrics.group_lookup("cached");
+      return Some(entry.groups.clone());
+    }
+
+    match *self.unavailable_until.lock().unwrap_or_else(|e| e.into_inner()) {
+      Some(until) if until > Instant::now() => {
+        self.metrics.group_lookup("skipped");
+        return None;
+      },
+      _ => ()
+    }
+
+    let groups = match self.directory.groups(username) {
+      Ok(groups) => groups,
+      Err(e)     => {
+        error!("Unable to look up the groups of {}, leaving the directory alone for {:?}: {}", username, self.retry_after, e);
+        self.metrics.group_lookup("error");
+        *self.unavailable_until.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now() + self.retry_after);
+        return None;
+      }
+    };
+    self.metrics.group_lookup(if groups.is_empty() { "not_found" } else { "found" });
+
+    self.remember(username, &groups);
+    Some(groups)
+  }
+
+  fn remember(&self, username: &str, groups: &[String]) {
+    if self.capacity == 0 {
+      return;
+    }
+
+    let now         = Instant::now();
+    let mut entries = self.lock();
+    if entries.len() >= self.capacity {
+      entries.retain(|_, entry| entry.expires_at > now);
+    }
+
+    // Still full of fresh entries, make room by dropping the one closest to expiring
+    if entries.len() >= self.capacity {
+      let oldest = entries.iter().min_by_key(|(_, entry)| entry.expires_at).map(|(username, _)| username.to_owned());
+      if let Some(oldest) = oldest {
+        entries.remove(&oldest);
+      }
+    }
+
+    entries.insert(username.to_owned(), Entry { groups: groups.to_vec(), expires_at: now + self.ttl });
+  }
+
+  /// Locks the entries, carrying on after a panic in another thread since they are always left consistent.
+  fn lock(&self) -> MutexGuard<HashMap<String, Entry>> {
+    self.entries.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+/// Name of a group: the CN when the value is a DN such as `cn=admins,ou=groups,dc=example,dc=com`, else the value.
+fn group_name(value: &str) -> String {
+  let value = value.trim();
+  match value.get(..3) {
+    Some(attribute) if attribute.eq_ignore_ascii_case("cn=") => (),
+    _ => return value.to_owned()
+  }
+
+  let mut name    = String::new();
+  let mut escaped = false;
+  for c in value[3..].chars() {
+    match (c, escaped) {
+      ('\\', false) => escaped = true,
+      (',', false)  => break,
+      (c, _)        => {
+        name.push(c);
+        escaped = false;
+      }
+    }
+  }
+  name
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use super::*;
+
+  /// Directory answering from a fixed list, counting how often it is asked.
+  struct Mock {
+    users: Vec<(&'static str, Vec<String>)>,
+    lookups: Arc<AtomicUsize>
+  }
+
+  impl Directory for Mock {
+    fn groups(&self, username: &str) -> Fallible<Vec<String>> {
+      self.lookups.fetch_add(1, Ordering::SeqCst);
+      match username {
+        "broken" => Err(format_err!("connection refused")),
+        _        => Ok(self.users.iter().find(|(user, _)| *user == username).map(|(_, groups)| groups.clone()).unwrap_or_default())
+      }
+    }
+  }
+
+  fn lookup_with(capacity: usize) -> (GroupLookup, Arc<AtomicUsize>) {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let mock    = Mock { users: vec![("kitty", vec!["admins".into(), "ops".into()])], lookups: lookups.clone() };
+    (GroupLookup::new(Box::new(mock), "ldap:".into(), Duration::from_secs(60), capacity, Duration::from_secs(60), Metrics::new().unwrap()), lookups)
+  }
+
+  fn user(username: &str, groups: Option<Vec<String>>) -> UserInfo {
+    UserInfo { username: Some(username.into()), groups, ..Default::default() }
+  }
+
+  speculate! {
+    it "adds directory groups to the user's own" {
+      let (lookup, _) = lookup_with(10);
+      let mut kitty   = user("kitty", Some(vec!["developers".into(), "ldap:ops".into()]));
+      assert!(lookup.enrich(&mut kitty));
+      assert_eq!(kitty.groups, Some(vec!["developers".into(), "ldap:ops".into(), "ldap:admins".into()]));
+
+      let mut puppy = user("puppy", None);
+      assert!(lookup.enrich(&mut puppy));
+      assert_eq!(puppy.groups, None);
+    }
+
+    it "caches lookups" {
+      let (lookup, lookups) = lookup_with(10);
+      for username in &["kitty", "kitty", "puppy", "puppy"] {
+        lookup.enrich(&mut user(username, None));
+      }
+      assert_eq!(lookups.load(Ordering::SeqCst), 2);
+
+      let (lookup, lookups) = lookup_with(0);
+      lookup.enrich(&mut user("kitty", None));
+      lookup.enrich(&mut user("kitty", None));
+      assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    it "leaves a failing directory alone for a while" {
+      let (lookup, lookups) = lookup_with(10);
+      let mut broken        = user("broken", Some(vec!["developers".into()]));
+      assert!(!lookup.enrich(&mut broken));
+      assert_eq!(broken.groups, Some(vec!["developers".into()]));
+
+      // Neither retried for the failing user nor asked about anyone else until `retry_after` has passed
+      assert!(!lookup.enrich(&mut user("broken", None)));
+      assert!(!lookup.enrich(&mut user("kitty", None)));
+      assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    }
+
+    it "gives up on lookups past their deadline" {
+      assert!(remaining(Instant::now() + Duration::from_secs(5)).is_ok());
+      assert!(remaining(Instant::now()).is_err());
+    }
+
+    it "reduces group DNs to their CN" {
+      assert_eq!(group_name("cn=admins,ou=groups,dc=example,dc=com"), "admins");
+      assert_eq!(group_name("CN=Smith\\, Ops,ou=groups,dc=example,dc=com"), "Smith, Ops");
+      assert_eq!(group_name("developers"), "developers");
+    }
+  }
+}
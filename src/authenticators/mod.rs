@@ -0,0 +1,315 @@
+use chrono::NaiveDateTime;
+use failure::{Fallible, format_err};
+use jsonwebtoken::dangerous_unsafe_decode;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::settings::{AuthenticatorConfig, Settings};
+use crate::signing::KeyRing;
+use crate::server::{HttpError, Metrics};
+use crate::kubernetes::authentication::v1::{TokenReviewSpec, UserInfo};
+
+mod claims;
+pub use claims::ClaimMapper;
+
+mod jwt;
+pub use jwt::JwtAuthenticator;
+
+mod static_tokens;
+pub use static_tokens::StaticTokenAuthenticator;
+
+mod oidc;
+pub use oidc::OidcAuthenticator;
+
+mod ldap;
+pub use ldap::{Directory, GroupLookup, LdapDirectory};
+
+/// What an authenticator learned about a token, kept for auditing and caching.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TokenDetails {
+  /// Unique token identifier (`jti` claim).
+  pub jti: Option<Uuid>,
+
+  /// Stored user owning the token.
+  pub user_id: Option<Uuid>,
+
+  /// When the token stops being valid.
+  pub expires_at: Option<NaiveDateTime>
+}
+
+/// Answer of a single authenticator.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+  /// The token is valid and belongs to this user, for these of the requested audiences.
+  /// Authenticators that are not audience aware claim none.
+  Authenticated(UserInfo, Vec<String>, TokenDetails),
+
+  /// The token belongs to this authenticator but is not valid, with a short machine readable reason.
+  Denied(&'static str, TokenDetails),
+
+  /// The token is not one this authenticator knows about, the next one should decide.
+  NotMine
+}
+
+impl Outcome {
+  /// Label used in logs and metrics.
+  pub fn label(&self) -> &'static str {
+    match self {
+      Outcome::Authenticated(..) => "authenticated",
+      Outcome::Denied(..)        => "denied",
+      Outcome::NotMine           => "not_mine"
+    }
+  }
+}
+
+/// A way of authenticating bearer tokens.
+/// Implementations are called from blocking threads and may do I/O.
+pub trait Authenticator: Send + Sync {
+  /// Name identifying the authenticator in logs and metrics.
+  fn name(&self) -> &str;
+
+  /// Reviews a token.
+  ///
+  /// # Arguments
+  /// * `spec` - TokenReview spec holding the token and requested audiences.
+  fn authenticate(&self, spec: &TokenReviewSpec) -> Result<Outcome, HttpError>;
+}
+
+/// Decision the chain reached, it always decides so a token nobody knows is denied.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decision {
+  /// The token is valid and belongs to this user, for these of the requested audiences.
+  Authenticated(UserInfo, Vec<String>, TokenDetails),
+
+  /// The token is not valid, with a short machine readable reason.
+  Denied(&'static str, TokenDetails)
+}
+
+impl Decision {
+  /// Label used in logs and metrics.
+  pub fn label(&self) -> &'static str {
+    match self {
+      Decision::Authenticated(..) => "authenticated",
+      Decision::Denied(..)        => "denied"
+    }
+  }
+}
+
+/// Final answer of the chain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Review {
+  /// Authenticator that gave the answer, if any did.
+  pub authenticator: Option<String>,
+  pub decision: Decision,
+
+  /// The user's directory groups could not be looked up, so the answer must not be cached.
+  pub degraded: bool
+}
+
+/// Ordered authenticators, the first to authenticate or deny a token decides.
+/// Authenticated users can then have their groups looked up in a directory.
+#[derive(Clone)]
+pub struct Chain {
+  authenticators: Arc<Vec<Box<dyn Authenticator>>>,
+  groups: Option<Arc<GroupLookup>>,
+  metrics: Metrics
+}
+
+impl Chain {
+  /// Builds the chain configured in settings.
+  ///
+  /// # Arguments
+  /// * `settings` - Settings to use.
+  /// * `database` - Database used by authenticators that need one.
+  /// * `keys`     - Key ring verifying tokens issued by this service.
+  /// * `metrics`  - Metrics to record each step in.
+  pub fn from_settings(settings: &Settings, database: &Database, keys: &KeyRing, metrics: &Metrics) -> Fallible<Self> {
+    let mut authenticators: Vec<Box<dyn Authenticator>> = Vec::new();
+
+    for config in &settings.authenticators {
+      match config {
+        AuthenticatorConfig::Jwt(mapping) => authenticators.push(Box::new(JwtAuthenticator::new(keys.clone(), database.clone(), mapping))),
+        AuthenticatorConfig::StaticTokens { path, reload_interval } => {
+          authenticators.push(Box::new(StaticTokenAuthenticator::new(path, *reload_interval)?))
+        },
+        AuthenticatorConfig::Oidc(oidc) => authenticators.push(Box::new(OidcAuthenticator::new(oidc)?))
+      }
+    }
+
+    if authenticators.is_empty() {
+      return Err(format_err!("at least one authenticator must be configured"));
+    }
+
+    info!("Authenticating with {}", authenticators.iter().map(|authenticator| authenticator.name()).collect::<Vec<_>>().join(", "));
+    let chain = Self::new(authenticators, metrics.clone());
+
+    match settings.ldap {
+      Some(ref ldap) => Ok(chain.with_groups(GroupLookup::from_settings(ldap, metrics)?)),
+      None           => Ok(chain)
+    }
+  }
+
+  /// Creates a chain.
+  ///
+  /// # Arguments
+  /// * `authenticators` - Authenticators, in the order they are asked.
+  /// * `metrics`        - Metrics to record each step in.
+  pub fn new(authenticators: Vec<Box<dyn Authenticator>>, metrics: Metrics) -> Self {
+    Chain { authenticators: Arc::new(authenticators), groups: None, metrics }
+  }
+
+  /// Looks up the groups of authenticated users.
+  ///
+  /// # Arguments
+  /// * `groups` - Lookup adding directory groups to users.
+  pub fn with_groups(mut self, groups: GroupLookup) -> Self {
+    self.groups = Some(Arc::new(groups));
+    self
+  }
+
+  /// Asks each authenticator in turn until one authenticates or denies the token.
+  /// An authenticator failing does not stop the chain, since the token may belong to a later one, whose answer then
+  /// decides. The failure is only returned if no later authenticator knows the token.
+  ///
+  /// # Arguments
+  /// * `spec`        - TokenReview spec holding the token and requested audiences.
+  /// * `fingerprint` - Fingerprint of the token, for logging.
+  pub fn authenticate(&self, spec: &TokenReviewSpec, fingerprint: &str) -> Result<Review, HttpError> {
+    let mut failure = None;
+
+    for authenticator in self.authenticators.iter() {
+      let started = Instant::now();
+      let result  = authenticator.authenticate(spec);
+      let label   = match result {
+        Ok(ref outcome) => outcome.label(),
+        Err(_)          => "error"
+      };
+      self.metrics.authenticator_step(authenticator.name(), label, started.elapsed());
+
+      let mut decision = match result {
+        Ok(Outcome::Authenticated(user, audiences, details)) => Decision::Authenticated(user, audiences, details),
+        Ok(Outcome::Denied(reason, details))                 => Decision::Denied(reason, details),
+        Ok(Outcome::NotMine) => {
+          debug!("Authenticator {} does not know token {}", authenticator.name(), fingerprint);
+          continue;
+        },
+        Err(e) => {
+          warn!("Authenticator {} failed on token {}: {:?}", authenticator.name(), fingerprint, e);
+          failure = Some(e);
+          continue;
+        }
+      };
+
+      debug!("Authenticator {} {} token {}", authenticator.name(), decision.label(), fingerprint);
+      let mut degraded = false;
+      if let Some(ref groups) = self.groups {
+        if let Decision::Authenticated(ref mut user, ..) = decision {
+          degraded = !groups.enrich(user);
+        }
+      }
+      return Ok(Review { authenticator: Some(authenticator.name().to_owned()), decision, degraded });
+    }
+
+    match failure {
+      Some(e) => Err(e),
+      None    => Ok(Review { authenticator: None, decision: Decision::Denied("unknown_token", TokenDetails::default()), degraded: false })
+    }
+  }
+}
+
+/// Unverified claims used to tell which issuer a JWT claims to come from.
+#[derive(Deserialize)]
+struct Issuer {
+  iss: Option<String>
+}
+
+/// The `iss` claim of a JWT, without checking its signature.
+/// Only good for picking the authenticator that should verify the token.
+fn unverified_issuer(token: &str) -> Option<String> {
+  dangerous_unsafe_decode::<Issuer>(token).ok().and_then(|data| data.claims.iss)
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use std::time::Duration;
+  use super::*;
+
+  struct Fixed(&'static str, fn() -> Result<Outcome, HttpError>);
+
+  impl Authenticator for Fixed {
+    fn name(&self) -> &str {
+      self.0
+    }
+
+    fn authenticate(&self, _spec: &TokenReviewSpec) -> Result<Outcome, HttpError> {
+      (self.1)()
+    }
+  }
+
+  fn chain_of(authenticators: Vec<Box<dyn Authenticator>>) -> Chain {
+    Chain::new(authenticators, Metrics::new().unwrap())
+  }
+
+  fn authenticated() -> Result<Outcome, HttpError> {
+    Ok(Outcome::Authenticated(UserInfo { username: Some("kitty".into()), ..Default::default() }, vec![], TokenDetails::default()))
+  }
+
+  struct Unreachable;
+
+  impl Directory for Unreachable {
+    fn groups(&self, _username: &str) -> Fallible<Vec<String>> {
+      Err(format_err!("connection refused"))
+    }
+  }
+
+  speculate! {
+    it "skips authenticators that do not know the token" {
+      let chain  = chain_of(vec![Box::new(Fixed("first", || Ok(Outcome::NotMine))), Box::new(Fixed("second", authenticated))]);
+      let review = chain.authenticate(&TokenReviewSpec::default(), "").unwrap();
+      assert_eq!(review.authenticator, Some("second".to_owned()));
+      assert_eq!(review.decision.label(), "authenticated");
+    }
+
+    it "stops at the first denial" {
+      let chain  = chain_of(vec![Box::new(Fixed("first", || Ok(Outcome::Denied("nope", TokenDetails::default())))), Box::new(Fixed("second", authenticated))]);
+      let review = chain.authenticate(&TokenReviewSpec::default(), "").unwrap();
+      assert_eq!(review.authenticator, Some("first".to_owned()));
+      assert_eq!(review.decision.label(), "denied");
+    }
+
+    it "carries on past failures" {
+      let chain = chain_of(vec![Box::new(Fixed("first", || Err(HttpError::InternalServerError))), Box::new(Fixed("second", authenticated))]);
+      assert_eq!(chain.authenticate(&TokenReviewSpec::default(), "").unwrap().decision.label(), "authenticated");
+
+      let chain  = chain_of(vec![Box::new(Fixed("first", || Err(HttpError::InternalServerError))), Box::new(Fixed("second", || Ok(Outcome::Denied("nope", TokenDetails::default()))))]);
+      let review = chain.authenticate(&TokenReviewSpec::default(), "").unwrap();
+      assert_eq!(review.authenticator, Some("second".to_owned()));
+      assert_eq!(review.decision.label(), "denied");
+
+      let chain = chain_of(vec![Box::new(Fixed("first", || Err(HttpError::InternalServerError))), Box::new(Fixed("second", || Ok(Outcome::NotMine)))]);
+      assert!(chain.authenticate(&TokenReviewSpec::default(), "").is_err());
+    }
+
+    it "flags users whose groups could not be looked up" {
+      let metrics = Metrics::new().unwrap();
+      let groups  = GroupLookup::new(Box::new(Unreachable), String::new(), Duration::from_secs(60), 10, Duration::from_secs(60), metrics.clone());
+      let chain   = Chain::new(vec![Box::new(Fixed("first", authenticated))], metrics).with_groups(groups);
+
+      let review = chain.authenticate(&TokenReviewSpec::default(), "").unwrap();
+      assert_eq!(review.decision.label(), "authenticated");
+      assert!(review.degraded);
+      assert!(!chain_of(vec![Box::new(Fixed("first", authenticated))]).authenticate(&TokenReviewSpec::default(), "").unwrap().degraded);
+    }
+
+    it "denies tokens nobody knows" {
+      let chain  = chain_of(vec![Box::new(Fixed("first", || Ok(Outcome::NotMine)))]);
+      let review = chain.authenticate(&TokenReviewSpec::default(), "").unwrap();
+      assert_eq!(review.authenticator, None);
+      assert_eq!(review.decision, Decision::Denied("unknown_token", TokenDetails::default()));
+    }
+  }
+}
@@ -0,0 +1,420 @@
+use actix_rt::{System, SystemRunner};
+use actix_web::client::Client;
+use failure::{Fallible, format_err};
+use futures::future::{Either, Future, err};
+use jsonwebtoken::{decode_header, Header, Validation};
+use serde_json::{Map, Value};
+use std::fs;
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::settings::{ClaimMapping, OidcConfig};
+use crate::signing::{JwkSet, SigningKey};
+use crate::server::HttpError;
+use crate::kubernetes::authentication::v1::{TokenReviewSpec, UserInfo};
+use super::{unverified_issuer, Authenticator, ClaimMapper, Outcome, TokenDetails};
+
+/// Seconds between reloads of the JWKS when `jwks_refresh_interval` is not set.
+const DEFAULT_REFRESH_INTERVAL: u64 = 300;
+
+/// How long fetching the JWKS from its URL may take.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest JWKS document accepted from the provider.
+const MAX_JWKS_SIZE: usize = 1 << 20;
+
+/// Seconds of clock skew with the provider tolerated when checking `exp` and `nbf`.
+const LEEWAY: i64 = 30;
+
+/// Least time between two refetches of the JWKS caused by tokens naming an unknown `kid`.
+const REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where the provider's keys are read from.
+enum Source {
+  File(String),
+  Url(String)
+}
+
+/// Authenticates ID tokens of an upstream OpenID Connect provider, verified against the provider's published keys.
+/// Tokens claiming another issuer are left to the next authenticator.
+/// A token naming a key that is not known yet makes the keys reload early, at most once per `REFETCH_INTERVAL`, so
+/// tokens signed with a freshly rotated key are accepted without waiting for the next refresh.
+pub struct OidcAuthenticator {
+  config: OidcConfig,
+  mapper: ClaimMapper,
+  keys: Arc<RwLock<Vec<SigningKey>>>,
+
+  /// Asks the refresh thread to reload the keys now, it answers on the given channel once done.
+  refetch: Mutex<mpsc::Sender<mpsc::Sender<()>>>,
+  last_refetch: Mutex<Option<Instant>>
+}
+
+impl OidcAuthenticator {
+  /// Loads the provider's keys and keeps refreshing them in the background.
+  /// Startup fails when the keys cannot be loaded, later failures keep the previous keys.
+  ///
+  /// # Arguments
+  /// * `config` - OIDC settings to use.
+  pub fn new(config: &OidcConfig) -> Fallible<Self> {
+    let source = match (&config.jwks_file, &config.jwks_url) {
+      (Some(path), _)   => Source::File(path.to_owned()),
+      (None, Some(url)) => Source::Url(url.to_owned()),
+      (None, None)      => return Err(format_err!("OIDC issuer {} needs a jwks_file or a jwks_url", config.issuer))
+    };
+
+    let interval = match config.jwks_refresh_interval.unwrap_or(DEFAULT_REFRESH_INTERVAL) {
+      0       => None,
+      seconds => Some(Duration::from_secs(seconds))
+    };
+
+    let keys      = Arc::new(RwLock::new(Vec::new()));
+    let refresher = Refresher { source, keys: keys.clone(), interval, system: None };
+
+    // Keys are loaded on the refresh thread, which runs its own event loop to fetch them
+    let (loaded, first)     = mpsc::channel();
+    let (refetch, requests) = mpsc::channel();
+    thread::Builder::new()
+      .name("jwks-refresh".to_owned())
+      .spawn(move || refresher.run(loaded, requests))?;
+
+    let count = first.recv()??;
+    info!("Loaded {} key(s) of OIDC issuer {}", count, config.issuer);
+
+    Ok(OidcAuthenticator {
+      config: config.clone(),
+      mapper: mapper(&config.issuer, &config.mapping),
+      keys,
+      refetch: Mutex::new(refetch),
+      last_refetch: Mutex::new(None)
+    })
+  }
+
+  /// Whether the token names a key that is not among the provider's known keys.
+  fn unknown_kid(&self, header: &Header) -> bool {
+    let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+    header.kid.as_ref().map_or(false, |kid| keys.iter().all(|key| key.kid != *kid))
+  }
+
+  /// Reloads the keys right away and waits for them, unless that was done less than `REFETCH_INTERVAL` ago.
+  /// Returns whether the keys were reloaded.
+  fn refetch(&self) -> bool {
+    {
+      let mut last_refetch = self.last_refetch.lock().unwrap_or_else(|e| e.into_inner());
+      match *last_refetch {
+        Some(at) if at.elapsed() < REFETCH_INTERVAL => return false,
+        _ => *last_refetch = Some(Instant::now())
+      }
+    }
+
+    let (done, reloaded) = mpsc::channel();
+    if self.refetch.lock().unwrap_or_else(|e| e.into_inner()).send(done).is_err() {
+      return false;
+    }
+    reloaded.recv_timeout(FETCH_TIMEOUT + Duration::from_secs(1)).is_ok()
+  }
+
+  /// Verifies a token with the provider's keys, returning its claims.
+  fn verify(&self, token: &str, header: &Header) -> Option<Map<String, Value>> {
+    let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+
+    keys.iter()
+      .filter(|key| key.algorithm == header.alg && header.kid.as_ref().map_or(true, |kid| *kid == key.kid))
+      .filter_map(|key| {
+        let validation = Validation {
+          iss: Some(self.config.issuer.to_owned()),
+          leeway: LEEWAY,
+          validate_nbf: true,
+          ..Validation::new(key.algorithm)
+        };
+        key.verify_with::<Map<String, Value>>(token, &validation).ok()
+      })
+      .map(|data| data.claims)
+      .next()
+  }
+
+  /// Maps verified claims to the user, or to the reason the token is denied.
+  fn user_info(&self, claims: &Map<String, Value>) -> Result<UserInfo, &'static str> {
+    if !issued_to(claims.get("aud"), &self.config.client_id) {
+      return Err("audience_mismatch");
+    }
+
+    // Emails are only trusted as usernames once the provider verified them
+    if self.mapper.username_claim() == Some("email") && claims.get("email_verified") == Some(&Value::Bool(false)) {
+      return Err("unverified_email");
+    }
+
+    let mut user = UserInfo::default();
+    self.mapper.apply(claims, &mut user)?;
+    Ok(user)
+  }
+}
+
+impl Authenticator for OidcAuthenticator {
+  fn name(&self) -> &str {
+    "oidc"
+  }
+
+  fn authenticate(&self, spec: &TokenReviewSpec) -> Result<Outcome, HttpError> {
+    let header = match decode_header(&spec.token) {
+      Ok(header) => header,
+      Err(_)     => return Ok(Outcome::NotMine)
+    };
+
+    if unverified_issuer(&spec.token).as_ref() != Some(&self.config.issuer) {
+      return Ok(Outcome::NotMine);
+    }
+
+    let mut verified = self.verify(&spec.token, &header);
+    if verified.is_none() && self.unknown_kid(&header) && self.refetch() {
+      debug!("Reloaded the keys of OIDC issuer {} for an unknown kid", self.config.issuer);
+      verified = self.verify(&spec.token, &header);
+    }
+
+    let claims = match verified {
+      Some(claims) => claims,
+      None         => return Ok(Outcome::Denied("invalid_token", TokenDetails::default()))
+    };
+
+    match self.user_info(&claims) {
+      Ok(user)    => Ok(Outcome::Authenticated(user, vec![], TokenDetails::default())),
+      Err(reason) => Ok(Outcome::Denied(reason, TokenDetails::default()))
+    }
+  }
+}
+
+/// Mapper taking the username from `sub` by default, prefixed with the issuer to keep it apart from users of other
+/// issuers like the kube-apiserver does. Emails are left unprefixed.
+fn mapper(issuer: &str, mapping: &ClaimMapping) -> ClaimMapper {
+  let prefix = match mapping.username_claim {
+    Some(ref claim) if claim == "email" => String::new(),
+    _                                   => format!("{}#", issuer)
+  };
+  ClaimMapper::new(mapping, Some("sub"), &prefix)
+}
+
+/// Whether an `aud` claim, a string or a list of strings, names the client.
+fn issued_to(aud: Option<&Value>, client_id: &str) -> bool {
+  match aud {
+    Some(Value::String(aud))      => aud == client_id,
+    Some(Value::Array(audiences)) => audiences.iter().any(|aud| aud.as_str() == Some(client_id)),
+    _ => false
+  }
+}
+
+/// Background loop reloading the provider's keys.
+struct Refresher {
+  source: Source,
+  keys: Arc<RwLock<Vec<SigningKey>>>,
+  interval: Option<Duration>,
+
+  /// Event loop used to fetch the JWKS URL, created on first use.
+  system: Option<SystemRunner>
+}
+
+impl Refresher {
+  /// Loads the keys, reports the outcome to `loaded`, then keeps reloading them unless that first load failed:
+  /// every `interval`, and whenever the authenticator asks through `requests`.
+  fn run(mut self, loaded: mpsc::Sender<Fallible<usize>>, requests: mpsc::Receiver<mpsc::Sender<()>>) {
+    let first  = self.reload();
+    let failed = first.is_err();
+    let _      = loaded.send(first);
+
+    if failed {
+      return;
+    }
+
+    loop {
+      let request = match self.interval {
+        Some(interval) => requests.recv_timeout(interval),
+        None           => requests.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected)
+      };
+
+      let done = match request {
+        Ok(done)                                  => Some(done),
+        Err(mpsc::RecvTimeoutError::Timeout)      => None,
+        Err(mpsc::RecvTimeoutError::Disconnected) => return
+      };
+
+      match self.reload() {
+        Ok(count) => debug!("Reloaded {} OIDC key(s)", count),
+        Err(e)    => error!("Unable to reload OIDC keys, keeping the previous ones: {}", e)
+      }
+
+      if let Some(done) = done {
+        let _ = done.send(());
+      }
+    }
+  }
+
+  /// Replaces the keys with the current JWKS, returning how many keys it holds.
+  fn reload(&mut self) -> Fallible<usize> {
+    let jwks = match self.source {
+      Source::File(ref path) => serde_json::from_str::<JwkSet>(&fs::read_to_string(path)?)?,
+      Source::Url(ref url)   => fetch(url, &mut self.system)?
+    };
+
+    let keys  = usable_keys(&jwks)?;
+    let count = keys.len();
+    *self.keys.write().unwrap_or_else(|e| e.into_inner()) = keys;
+    Ok(count)
+  }
+}
+
+/// Downloads a JWKS.
+///
+/// # Arguments
+/// * `url`    - URL of the JWKS.
+/// * `system` - Event loop to run the request on, created if needed.
+fn fetch(url: &str, system: &mut Option<SystemRunner>) -> Fallible<JwkSet> {
+  let system = system.get_or_insert_with(|| System::new("jwks-refresh"));
+  let url    = url.to_owned();
+
+  system.block_on(
+    Client::default()
+      .get(url.as_str())
+      .timeout(FETCH_TIMEOUT)
+      .send()
+      .map_err(|e| format_err!("{}", e))
+      .and_then(move |mut response| {
+        if !response.status().is_success() {
+          return Either::A(err(format_err!("{} answered {}", url, response.status())));
+        }
+        Either::B(response.json::<JwkSet>().limit(MAX_JWKS_SIZE).map_err(|e| format_err!("{}", e)))
+      })
+  )
+}
+
+/// Signature keys of a JWKS, skipping the ones that cannot be used.
+fn usable_keys(jwks: &JwkSet) -> Fallible<Vec<SigningKey>> {
+  let keys: Vec<SigningKey> = jwks.keys.iter()
+    .filter(|jwk| jwk.key_use.is_empty() || jwk.key_use == "sig")
+    .filter_map(|jwk| match SigningKey::from_jwk(jwk) {
+      Ok(key) => Some(key),
+      Err(e)  => {
+        warn!("Skipping OIDC key: {}", e);
+        None
+      }
+    })
+    .collect();
+
+  if keys.is_empty() {
+    return Err(format_err!("the JWKS holds no usable signature key"));
+  }
+  Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use chrono::Utc;
+  use jsonwebtoken::Algorithm;
+  use openssl::rsa::Rsa;
+  use serde_json::json;
+  use uuid::Uuid;
+  use super::*;
+
+  const ISSUER: &str = "https://idp.example.com";
+
+  fn signing_key() -> SigningKey {
+    let pem = Rsa::generate(2048).unwrap().private_key_to_pem().unwrap();
+    SigningKey::from_pem(Algorithm::RS256, None, Some(&pem), None).unwrap()
+  }
+
+  fn write_jwks(path: &std::path::Path, keys: &[&SigningKey]) {
+    fs::write(path, serde_json::to_string(&JwkSet { keys: keys.iter().map(|key| key.jwk().unwrap()).collect() }).unwrap()).unwrap();
+  }
+
+  /// Authenticator trusting the keys of a JWKS file, never reloaded on its own.
+  fn authenticator_from(path: &std::path::Path) -> OidcAuthenticator {
+    OidcAuthenticator::new(&OidcConfig {
+      issuer: ISSUER.into(),
+      client_id: "kubernetes".into(),
+      jwks_file: Some(path.to_string_lossy().into_owned()),
+      jwks_url: None,
+      jwks_refresh_interval: Some(0),
+      mapping: ClaimMapping { groups_claims: vec!["groups".into()], groups_prefix: Some("oidc:".into()), ..Default::default() }
+    }).unwrap()
+  }
+
+  /// Authenticator trusting the key, through a JWKS file.
+  fn authenticator(key: &SigningKey) -> OidcAuthenticator {
+    let path = std::env::temp_dir().join(format!("jwks-{}.json", Uuid::new_v4()));
+    write_jwks(&path, &[key]);
+
+    let authenticator = authenticator_from(&path);
+    fs::remove_file(&path).unwrap();
+    authenticator
+  }
+
+  fn review(authenticator: &OidcAuthenticator, token: String) -> Outcome {
+    authenticator.authenticate(&TokenReviewSpec { token, ..Default::default() }).unwrap()
+  }
+
+  fn claims(iss: &str, aud: Value) -> Value {
+    json!({ "iss": iss, "aud": aud, "sub": "kitty", "groups": ["admins", "ops"], "exp": Utc::now().timestamp() + 60 })
+  }
+
+  speculate! {
+    it "maps the claims of valid tokens to the user" {
+      let key           = signing_key();
+      let authenticator = authenticator(&key);
+      let token         = key.sign(&claims(ISSUER, json!(["kubernetes", "other"]))).unwrap();
+
+      let user = UserInfo {
+        username: Some(format!("{}#kitty", ISSUER)),
+        groups: Some(vec!["oidc:admins".into(), "oidc:ops".into()]),
+        ..Default::default()
+      };
+      assert_eq!(review(&authenticator, token), Outcome::Authenticated(user, vec![], TokenDetails::default()));
+    }
+
+    it "leaves tokens of other issuers to the next authenticator" {
+      let key           = signing_key();
+      let authenticator = authenticator(&key);
+
+      assert_eq!(review(&authenticator, key.sign(&claims("heimdallr", json!("kubernetes"))).unwrap()), Outcome::NotMine);
+      assert_eq!(review(&authenticator, "kitty".into()), Outcome::NotMine);
+    }
+
+    it "denies tokens for other clients or signed by other keys" {
+      let key           = signing_key();
+      let authenticator = authenticator(&key);
+
+      let token = key.sign(&claims(ISSUER, json!("other"))).unwrap();
+      assert_eq!(review(&authenticator, token), Outcome::Denied("audience_mismatch", TokenDetails::default()));
+
+      let mut forged = signing_key();
+      forged.kid = key.kid.to_owned();
+      let token = forged.sign(&claims(ISSUER, json!("kubernetes"))).unwrap();
+      assert_eq!(review(&authenticator, token), Outcome::Denied("invalid_token", TokenDetails::default()));
+    }
+
+    it "denies tokens that are not valid yet" {
+      let key           = signing_key();
+      let authenticator = authenticator(&key);
+
+      let mut claims = claims(ISSUER, json!("kubernetes"));
+      claims["nbf"]  = json!(Utc::now().timestamp() + 3600);
+      assert_eq!(review(&authenticator, key.sign(&claims).unwrap()), Outcome::Denied("invalid_token", TokenDetails::default()));
+    }
+
+    it "reloads the keys once for a token naming an unknown key" {
+      let (old, rotated, unknown) = (signing_key(), signing_key(), signing_key());
+      let path                    = std::env::temp_dir().join(format!("jwks-{}.json", Uuid::new_v4()));
+      write_jwks(&path, &[&old]);
+      let authenticator = authenticator_from(&path);
+
+      // The provider rotated its key, the new one is picked up without waiting for a refresh
+      write_jwks(&path, &[&old, &rotated]);
+      assert_eq!(review(&authenticator, rotated.sign(&claims(ISSUER, json!("kubernetes"))).unwrap()).label(), "authenticated");
+
+      // Another unknown key right after does not reload the keys again
+      write_jwks(&path, &[&old, &rotated, &unknown]);
+      let token = unknown.sign(&claims(ISSUER, json!("kubernetes"))).unwrap();
+      assert_eq!(review(&authenticator, token), Outcome::Denied("invalid_token", TokenDetails::default()));
+
+      fs::remove_file(&path).unwrap();
+    }
+  }
+}
@@ -0,0 +1,261 @@
+use failure::{Fallible, format_err};
+use openssl::sha::sha256;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::token;
+use crate::server::HttpError;
+use crate::kubernetes::authentication::v1::{TokenReviewSpec, UserInfo};
+use super::{Authenticator, Outcome, TokenDetails};
+
+/// Seconds between checks of the token file when `reload_interval` is not set.
+const DEFAULT_RELOAD_INTERVAL: u64 = 10;
+
+/// A token listed in the file.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct StaticToken {
+  /// Hash of the token, as printed by `token hash`.
+  token: String,
+  user: String,
+  uid: String,
+
+  #[serde(default)]
+  groups: Vec<String>
+}
+
+struct State {
+  tokens: HashMap<String, StaticToken>,
+
+  /// Hash of the file the tokens were loaded from, so edits are noticed whatever its modification time says.
+  digest: [u8; 32],
+  checked: Instant
+}
+
+/// Authenticates tokens listed in a file, in the format of the kube-apiserver's `--token-auth-file`
+/// (`token,user,uid,"group1,group2"`) or as a YAML list, except that tokens are stored as hashes.
+/// Needs no database, so it keeps working while Postgres is down.
+pub struct StaticTokenAuthenticator {
+  path: String,
+  interval: Duration,
+  state: RwLock<State>
+}
+
+impl StaticTokenAuthenticator {
+  /// Loads the token file.
+  ///
+  /// # Arguments
+  /// * `path`            - Path of the CSV, or YAML when it ends in `.yaml` or `.yml`.
+  /// * `reload_interval` - Seconds between checks of the file for changes.
+  pub fn new(path: &str, reload_interval: Option<u64>) -> Fallible<Self> {
+    let contents = fs::read_to_string(path)?;
+    let tokens   = parse(path, &contents)?;
+    info!("Loaded {} static token(s) from {}", tokens.len(), path);
+
+    Ok(StaticTokenAuthenticator {
+      path: path.to_owned(),
+      interval: Duration::from_secs(reload_interval.unwrap_or(DEFAULT_RELOAD_INTERVAL)),
+      state: RwLock::new(State { tokens, digest: sha256(contents.as_bytes()), checked: Instant::now() })
+    })
+  }
+
+  /// Reloads the file if its contents changed since it was last loaded.
+  /// A file that fails to load is logged and the previous tokens are kept.
+  fn refresh(&self) {
+    if self.state.read().unwrap_or_else(|e| e.into_inner()).checked.elapsed() < self.interval {
+      return;
+    }
+
+    let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+    state.checked = Instant::now();
+
+    let contents = match fs::read_to_string(&self.path) {
+      Ok(contents) => contents,
+      Err(e)       => {
+        error!("Unable to read static tokens from {}, keeping the previous ones: {}", self.path, e);
+        return;
+      }
+    };
+
+    let digest = sha256(contents.as_bytes());
+    if digest == state.digest {
+      return;
+    }
+
+    match parse(&self.path, &contents) {
+      Ok(tokens) => {
+        info!("Reloaded {} static token(s) from {}", tokens.len(), self.path);
+        state.tokens = tokens;
+        state.digest = digest;
+      },
+      Err(e) => error!("Unable to reload static tokens from {}, keeping the previous ones: {}", self.path, e)
+    }
+  }
+}
+
+impl Authenticator for StaticTokenAuthenticator {
+  fn name(&self) -> &str {
+    "static_tokens"
+  }
+
+  fn authenticate(&self, spec: &TokenReviewSpec) -> Result<Outcome, HttpError> {
+    self.refresh();
+
+    let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+    match state.tokens.get(&token::hash(&spec.token)) {
+      Some(found) => {
+        let user = UserInfo {
+          username: Some(found.user.to_owned()),
+          uid: Some(found.uid.to_owned()),
+          groups: if found.groups.is_empty() { None } else { Some(found.groups.to_owned()) },
+          extra: None
+        };
+
+        Ok(Outcome::Authenticated(user, vec![], TokenDetails::default()))
+      },
+      None => Ok(Outcome::NotMine)
+    }
+  }
+}
+
+/// Parses the contents of a token file, refusing tokens that are not hashed.
+///
+/// # Arguments
+/// * `path`     - Path of the file, YAML when it ends in `.yaml` or `.yml` and CSV otherwise.
+/// * `contents` - Contents of the file.
+fn parse(path: &str, contents: &str) -> Fallible<HashMap<String, StaticToken>> {
+  let entries = if path.ends_with(".yaml") || path.ends_with(".yml") {
+    serde_yaml::from_str::<Vec<StaticToken>>(contents)?
+  }
+  else {
+    parse_csv(contents)?
+  };
+
+  let mut tokens = HashMap::new();
+  for entry in entries {
+    if !is_hash(&entry.token) {
+      return Err(format_err!("the token of {} is not a sha256 hash, store `token hash` output instead", entry.user));
+    }
+    if tokens.contains_key(&entry.token) {
+      return Err(format_err!("the token of {} is listed more than once", entry.user));
+    }
+    tokens.insert(entry.token.to_owned(), entry);
+  }
+  Ok(tokens)
+}
+
+/// Parses `token,user,uid[,"group1,group2"]` lines, skipping blank lines and `#` comments.
+fn parse_csv(contents: &str) -> Fallible<Vec<StaticToken>> {
+  let mut entries = Vec::new();
+
+  for (number, line) in contents.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let fields = split_csv(line).map_err(|e| format_err!("line {}: {}", number + 1, e))?;
+    if fields.len() < 3 {
+      return Err(format_err!("line {}: expected token,user,uid[,groups]", number + 1));
+    }
+
+    entries.push(StaticToken {
+      token: fields[0].to_owned(),
+      user: fields[1].to_owned(),
+      uid: fields[2].to_owned(),
+      groups: fields.get(3).map_or_else(Vec::new, |groups| {
+        groups.split(',').map(str::trim).filter(|group| !group.is_empty()).map(str::to_owned).collect()
+      })
+    });
+  }
+  Ok(entries)
+}
+
+/// Splits a CSV line into fields, honouring double quotes and `""` escapes inside them.
+fn split_csv(line: &str) -> Fallible<Vec<String>> {
+  let mut fields = vec![String::new()];
+  let mut quoted = false;
+  let mut chars  = line.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    match (c, quoted) {
+      ('"', true) if chars.peek() == Some(&'"') => {
+        chars.next();
+        fields.last_mut().unwrap().push('"');
+      },
+      ('"', _)     => quoted = !quoted,
+      (',', false) => fields.push(String::new()),
+      (c, _)       => fields.last_mut().unwrap().push(c)
+    }
+  }
+
+  if quoted {
+    return Err(format_err!("unterminated quote"));
+  }
+  Ok(fields.into_iter().map(|field| field.trim().to_owned()).collect())
+}
+
+/// Whether a value looks like the output of `token::hash`.
+fn is_hash(value: &str) -> bool {
+  match value.get(..7) {
+    Some("sha256:") => value.len() == 71 && value[7..].chars().all(|c| c.is_ascii_hexdigit()),
+    _ => false
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use uuid::Uuid;
+  use super::*;
+
+  /// Username a token authenticates as, if any.
+  fn username(authenticator: &StaticTokenAuthenticator, token: &str) -> Option<String> {
+    match authenticator.authenticate(&TokenReviewSpec { token: token.into(), ..Default::default() }).unwrap() {
+      Outcome::Authenticated(user, ..) => user.username,
+      _                                => None
+    }
+  }
+
+  speculate! {
+    it "parses token-auth-file lines" {
+      let hash    = token::hash("kitty");
+      let entries = parse_csv(&format!("# break glass\n{},admin,1,\"system:masters,ops\"\n\n{},ci,2\n", hash, token::hash("cat"))).unwrap();
+
+      assert_eq!(entries.len(), 2);
+      assert_eq!(entries[0], StaticToken { token: hash, user: "admin".into(), uid: "1".into(), groups: vec!["system:masters".into(), "ops".into()] });
+      assert!(entries[1].groups.is_empty());
+    }
+
+    it "rejects malformed lines" {
+      assert!(parse_csv("sha256:abc,admin").is_err());
+      assert!(parse_csv("sha256:abc,admin,1,\"ops").is_err());
+    }
+
+    it "only accepts hashed tokens" {
+      assert!(is_hash(&token::hash("kitty")));
+      assert!(!is_hash("kitty"));
+      assert!(!is_hash("sha256:kitty"));
+    }
+
+    it "reloads the file and keeps the previous tokens when it turns invalid" {
+      let path = std::env::temp_dir().join(format!("tokens-{}.csv", Uuid::new_v4()));
+      fs::write(&path, format!("{},admin,1\n", token::hash("kitty"))).unwrap();
+
+      let authenticator = StaticTokenAuthenticator::new(path.to_str().unwrap(), Some(0)).unwrap();
+      assert_eq!(username(&authenticator, "kitty"), Some("admin".into()));
+
+      // Rewritten within the same second, the modification time alone may not tell
+      fs::write(&path, format!("{},ci,2\n", token::hash("cat"))).unwrap();
+      assert_eq!(username(&authenticator, "kitty"), None);
+      assert_eq!(username(&authenticator, "cat"), Some("ci".into()));
+
+      fs::write(&path, "cat,admin,1\n").unwrap();
+      assert_eq!(username(&authenticator, "cat"), Some("ci".into()));
+
+      fs::remove_file(&path).unwrap();
+    }
+  }
+}
@@ -0,0 +1,335 @@
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use chrono::{Duration, Utc};
+use failure::{Fallible, format_err};
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use uuid::Uuid;
+
+use crate::token;
+use crate::db::Database;
+use crate::server::Server;
+use crate::settings::Settings;
+use crate::signing::{KeyRing, StorageKey};
+use crate::models::{Group, Token, User, UserGroup};
+
+/// Builds the command line interface.
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+  App::new("Heimdallr")
+    .about("API Authentication Service")
+    .version(crate_version!())
+    .arg(
+      Arg::with_name("config")
+        .long("config")
+        .short("c")
+        .value_name("FILE")
+        .help("Sets a custom config file")
+        .takes_value(true)
+        .global(true)
+    )
+    .subcommand(SubCommand::with_name("serve").about("Starts the HTTP server (default)"))
+    .subcommand(
+      SubCommand::with_name("migrate")
+        .about("Applies pending database migrations")
+        .arg(Arg::with_name("check").long("check").help("Only list pending migrations, failing if there are any"))
+    )
+    .subcommand(
+      SubCommand::with_name("token")
+        .about("Manages tokens")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+          SubCommand::with_name("issue")
+            .about("Issues a token and prints it")
+            .arg(Arg::with_name("user").long("user").value_name("USER").help("Username or id of the owner").required(true).takes_value(true))
+            .arg(Arg::with_name("ttl").long("ttl").value_name("TTL").help("Lifetime, eg 3600, 90m, 12h or 30d").default_value("24h").takes_value(true))
+            .arg(Arg::with_name("scope").long("scope").value_name("SCOPE").help("Scope to grant").takes_value(true).multiple(true).number_of_values(1))
+            .arg(Arg::with_name("audience").long("audience").value_name("AUDIENCE").help("Audience the token is intended for").takes_value(true).multiple(true).number_of_values(1))
+        )
+        .subcommand(
+          SubCommand::with_name("revoke")
+            .about("Revokes a token, or every token of a user")
+            .arg(Arg::with_name("id").value_name("TOKEN_ID").help("Token to revoke").required_unless("user").conflicts_with("user"))
+            .arg(Arg::with_name("user").long("user").value_name("USER").help("Revoke every token of this user").takes_value(true))
+            .arg(Arg::with_name("reason").long("reason").value_name("REASON").help("Why the token is revoked").takes_value(true))
+        )
+        .subcommand(
+          SubCommand::with_name("hash")
+            .about("Reads a token from stdin and prints its hash, for static token files")
+        )
+        .subcommand(
+          SubCommand::with_name("list")
+            .about("Lists tokens")
+            .arg(Arg::with_name("user").long("user").value_name("USER").help("Only list tokens of this user").takes_value(true))
+            .arg(Arg::with_name("all").long("all").help("Include expired and revoked tokens"))
+        )
+    )
+    .subcommand(
+      SubCommand::with_name("key")
+        .about("Manages signing keys")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+          SubCommand::with_name("encrypt")
+            .about("Reads a PEM private key from stdin and prints it encrypted, for the signing_keys table")
+            .arg(Arg::with_name("kid").long("kid").value_name("KID").help("Kid of the row the key is stored in").required(true).takes_value(true))
+        )
+    )
+    .subcommand(
+      SubCommand::with_name("user")
+        .about("Manages users")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+          SubCommand::with_name("create")
+            .about("Creates a user")
+            .arg(Arg::with_name("username").value_name("USERNAME").required(true))
+            .arg(Arg::with_name("extra").long("extra").value_name("KEY=VALUE").help("Extra attribute, may be repeated").takes_value(true).multiple(true).number_of_values(1))
+        )
+        .subcommand(
+          SubCommand::with_name("add-group")
+            .about("Adds a user to a group, creating the group if needed")
+            .arg(Arg::with_name("user").value_name("USER").help("Username or id").required(true))
+            .arg(Arg::with_name("group").value_name("GROUP").required(true))
+        )
+    )
+}
+
+/// Runs the command selected on the command line.
+///
+/// # Arguments
+/// * `arguments` - Parsed command line.
+/// * `settings`  - Settings to use.
+pub fn run(arguments: &ArgMatches, settings: &Settings) -> Fallible<()> {
+  match arguments.subcommand() {
+    ("migrate", Some(args)) => migrate(args, settings),
+    ("token", Some(args))  => match args.subcommand() {
+      ("issue", Some(args))  => issue_token(args, settings),
+      ("revoke", Some(args)) => revoke_token(args, settings),
+      ("list", Some(args))   => list_tokens(args, settings),
+      ("hash", Some(_))      => hash_token(),
+      _                      => unreachable!()
+    },
+    ("key", Some(args))    => match args.subcommand() {
+      ("encrypt", Some(args)) => encrypt_key(args, settings),
+      _                       => unreachable!()
+    },
+    ("user", Some(args))   => match args.subcommand() {
+      ("create", Some(args))    => create_user(args, settings),
+      ("add-group", Some(args)) => add_group(args, settings),
+      _                         => unreachable!()
+    },
+    _ => serve(settings)
+  }
+}
+
+/// Starts the HTTP server.
+fn serve(settings: &Settings) -> Fallible<()> {
+  let server = Server::from_settings(&settings)?;
+  server.start()?;
+  Ok(())
+}
+
+/// Applies pending migrations, printing each one that runs.
+fn migrate(args: &ArgMatches, settings: &Settings) -> Fallible<()> {
+  let database = Database::from_settings(&settings)?;
+
+  if args.is_present("check") {
+    let pending = database.pending_migrations()?;
+    for version in &pending {
+      println!("Pending {}", version);
+    }
+    if !pending.is_empty() {
+      return Err(format_err!("{} migration(s) pending", pending.len()));
+    }
+    println!("Database schema is up to date");
+    return Ok(());
+  }
+
+  let applied = database.migrate()?;
+  for version in &applied {
+    println!("Applied {}", version);
+  }
+  if applied.is_empty() {
+    println!("Database schema is up to date");
+  }
+  Ok(())
+}
+
+fn issue_token(args: &ArgMatches, settings: &Settings) -> Fallible<()> {
+  let database = Database::from_settings(&settings)?;
+  let keys     = KeyRing::from_settings(&settings, &database)?;
+  let conn     = database.pool.get()?;
+
+  if !keys.active.can_sign() {
+    return Err(format_err!("the active signing key {} has no private key", keys.active.kid));
+  }
+
+  let user       = find_user(args.value_of("user").unwrap_or_default(), &conn)?;
+  let ttl        = parse_ttl(args.value_of("ttl").unwrap_or_default())?;
+  let scopes     = values(args, "scope");
+  let audiences  = values(args, "audience");
+  let expires_at = (Utc::now() + ttl).naive_utc();
+
+  let token   = Token::new(user.id, scopes, audiences, &keys.issuer, expires_at, &conn)?;
+  let encoded = token::encode(&keys, &token.claims)?;
+
+  eprintln!("Issued token {} to {} (expires {})", token.id, user.username, token.expires_at);
+  println!("{}", encoded);
+  Ok(())
+}
+
+fn revoke_token(args: &ArgMatches, settings: &Settings) -> Fallible<()> {
+  let database = Database::from_settings(&settings)?;
+  let conn     = database.pool.get()?;
+  let reason   = args.value_of("reason").map(str::to_owned);
+
+  // No decision cache lives in this process, servers keep serving cached decisions for up to `cache.ttl` seconds
+  let revoked = match (args.value_of("id"), args.value_of("user")) {
+    (Some(id), _)   => vec![Token::revoke(Uuid::parse_str(id)?, reason, None, &conn)?],
+    (None, Some(u)) => Token::revoke_all_for_user(find_user(u, &conn)?.id, reason, None, &conn)?,
+    (None, None)    => unreachable!()
+  };
+
+  for token in &revoked {
+    println!("Revoked {}", token.id);
+  }
+  Ok(())
+}
+
+fn list_tokens(args: &ArgMatches, settings: &Settings) -> Fallible<()> {
+  let database = Database::from_settings(&settings)?;
+  let conn     = database.pool.get()?;
+
+  let owner = match args.value_of("user") {
+    Some(user) => Some(find_user(user, &conn)?.id),
+    None       => None
+  };
+
+  println!("{:<36}  {:<36}  {:<19}  {:<19}  {}", "ID", "USER", "EXPIRES", "REVOKED", "SCOPES");
+  for token in Token::list(owner, args.is_present("all"), &conn)? {
+    println!(
+      "{:<36}  {:<36}  {:<19}  {:<19}  {}",
+      token.id,
+      token.user_id,
+      token.expires_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+      token.revoked_at.map_or_else(|| "-".to_owned(), |at| at.format("%Y-%m-%d %H:%M:%S").to_string()),
+      token.claims.scopes.join(",")
+    );
+  }
+  Ok(())
+}
+
+fn hash_token() -> Fallible<()> {
+  let mut input = String::new();
+  io::stdin().read_line(&mut input)?;
+
+  let token = input.trim();
+  if token.is_empty() {
+    return Err(format_err!("no token given on stdin"));
+  }
+  println!("{}", token::hash(token));
+  Ok(())
+}
+
+fn encrypt_key(args: &ArgMatches, settings: &Settings) -> Fallible<()> {
+  let storage = StorageKey::from_settings(&settings.signing)?
+    .ok_or_else(|| format_err!("signing.database_key must be set to encrypt keys"))?;
+
+  let mut pem = Vec::new();
+  io::stdin().read_to_end(&mut pem)?;
+  if pem.is_empty() {
+    return Err(format_err!("no private key given on stdin"));
+  }
+  println!("{}", storage.encrypt(args.value_of("kid").unwrap_or_default(), &pem)?);
+  Ok(())
+}
+
+fn create_user(args: &ArgMatches, settings: &Settings) -> Fallible<()> {
+  let database = Database::from_settings(&settings)?;
+  let conn     = database.pool.get()?;
+
+  let mut extra: BTreeMap<String, Vec<String>> = BTreeMap::new();
+  for pair in values(args, "extra") {
+    let mut parts = pair.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+      (Some(key), Some(value)) if !key.is_empty() => extra.entry(key.to_owned()).or_default().push(value.to_owned()),
+      _ => return Err(format_err!("extra attributes must look like KEY=VALUE, got {:?}", pair))
+    }
+  }
+
+  let user = User::create(args.value_of("username").unwrap_or_default(), extra, &conn)?;
+  println!("Created user {} ({})", user.username, user.id);
+  Ok(())
+}
+
+fn add_group(args: &ArgMatches, settings: &Settings) -> Fallible<()> {
+  let database = Database::from_settings(&settings)?;
+  let conn     = database.pool.get()?;
+
+  let user  = find_user(args.value_of("user").unwrap_or_default(), &conn)?;
+  let group = Group::find_or_create(args.value_of("group").unwrap_or_default(), &conn)?;
+  UserGroup::add(&user, &group, &conn)?;
+
+  println!("Added {} to {}", user.username, group.name);
+  Ok(())
+}
+
+/// Finds a user by id or, failing that, by username.
+fn find_user(user: &str, conn: &diesel::pg::PgConnection) -> Fallible<User> {
+  let found = match Uuid::parse_str(user) {
+    Ok(id) => User::find(id, conn),
+    Err(_) => User::find_by_username(user, conn)
+  };
+  found.map_err(|_| format_err!("no user {:?}", user))
+}
+
+/// Collects every value of a repeatable argument.
+fn values(args: &ArgMatches, name: &str) -> Vec<String> {
+  args.values_of(name).map_or_else(Vec::new, |values| values.map(str::to_owned).collect())
+}
+
+/// Parses a lifetime in seconds, or with an `s`, `m`, `h` or `d` suffix.
+/// Lifetimes outside of what the API accepts are refused.
+fn parse_ttl(ttl: &str) -> Fallible<Duration> {
+  let (amount, unit) = match ttl.char_indices().last() {
+    Some((index, unit)) if unit.is_ascii_alphabetic() => (&ttl[..index], unit),
+    _ => (ttl, 's')
+  };
+
+  let amount: i64 = amount.parse().map_err(|_| format_err!("invalid ttl {:?}", ttl))?;
+  let unit        = match unit {
+    's' => 1,
+    'm' => 60,
+    'h' => 60 * 60,
+    'd' => 24 * 60 * 60,
+    _   => return Err(format_err!("invalid ttl unit in {:?}", ttl))
+  };
+
+  match amount.checked_mul(unit) {
+    Some(seconds) if token::validate_ttl(seconds).is_ok() => Ok(Duration::seconds(seconds)),
+    _ => Err(format_err!("ttl {:?} must be between {} and {} seconds", ttl, token::MIN_TTL, token::MAX_TTL))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  speculate! {
+    it "parses ttls with and without units" {
+      assert_eq!(parse_ttl("3600").unwrap(), Duration::hours(1));
+      assert_eq!(parse_ttl("90m").unwrap(), Duration::minutes(90));
+      assert_eq!(parse_ttl("30d").unwrap(), Duration::days(30));
+      assert!(parse_ttl("12y").is_err());
+      assert!(parse_ttl("h").is_err());
+    }
+
+    it "refuses ttls the API would refuse" {
+      assert_eq!(parse_ttl("1m").unwrap(), Duration::minutes(1));
+      assert_eq!(parse_ttl("365d").unwrap(), Duration::days(365));
+      assert!(parse_ttl("0").is_err());
+      assert!(parse_ttl("-1h").is_err());
+      assert!(parse_ttl("59s").is_err());
+      assert!(parse_ttl("366d").is_err());
+      assert!(parse_ttl("9223372036854775807d").is_err());
+    }
+  }
+}
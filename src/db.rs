@@ -6,9 +6,18 @@ pub use schema::*;
 
 use crate::settings::Settings;
 
-use failure::Fallible;
+use failure::{Fallible, format_err};
+use diesel::{Connection, RunQueryDsl};
+use diesel::dsl::sql;
 use diesel::pg::PgConnection;
 use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::result::Error as DieselError;
+use diesel::sql_types::Bool;
+use diesel_migrations::MigrationConnection;
+use std::collections::HashSet;
+
+// Versions and names of the embedded migrations, listed by build.rs
+include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
 
 /// Database object.
 #[derive(Clone)]
@@ -63,4 +72,85 @@ impl Database {
     let pool    = Pool::builder().build(manager)?;
     Ok(Database { pool })
   }
+
+  /// Checks that a connection can be checked out and answers a trivial query within a timeout.
+  ///
+  /// # Arguments
+  /// * `timeout` - Longest time to wait for a connection, and for the query.
+  pub fn ping(&self, timeout: std::time::Duration) -> Fallible<()> {
+    let conn = self.pool.get_timeout(timeout)?;
+
+    conn.transaction::<_, DieselError, _>(|| {
+      diesel::sql_query(format!("SET LOCAL statement_timeout = {}", timeout.as_millis())).execute(&conn)?;
+      diesel::sql_query("SELECT 1").execute(&conn)
+    })?;
+    Ok(())
+  }
+
+  /// Applies pending migrations.
+  /// Returns the names of the migrations that ran.
+  pub fn migrate(&self) -> Fallible<Vec<String>> {
+    let pending = self.pending_migrations()?;
+    let conn    = self.pool.get()?;
+
+    crate::embedded_migrations::run(&conn)?;
+    Ok(pending)
+  }
+
+  /// Names the migrations that have not been applied yet, comparing the embedded ones with those Diesel recorded.
+  /// Nothing is written, a database Diesel never ran against has every migration pending.
+  pub fn pending_migrations(&self) -> Fallible<Vec<String>> {
+    let conn = self.pool.get()?;
+
+    let recorded: bool = diesel::select(sql::<Bool>("to_regclass('__diesel_schema_migrations') IS NOT NULL")).get_result(&conn)?;
+    let applied = if recorded { conn.previously_run_migration_versions()? } else { HashSet::new() };
+
+    Ok(pending(MIGRATIONS, &applied))
+  }
+
+  /// Makes sure the schema is current before serving, applying pending migrations when `auto_migrate` is set.
+  ///
+  /// # Arguments
+  /// * `auto_migrate` - Whether pending migrations may be applied.
+  pub fn ensure_schema(&self, auto_migrate: bool) -> Fallible<()> {
+    if auto_migrate {
+      for version in self.migrate()? {
+        info!("Applied migration {}", version);
+      }
+      return Ok(());
+    }
+
+    let pending = self.pending_migrations()?;
+    if !pending.is_empty() {
+      return Err(format_err!(
+        "database schema is behind, pending migrations: {} (run `migrate` or set database.auto_migrate)",
+        pending.join(", ")
+      ));
+    }
+    Ok(())
+  }
+}
+
+/// Names of the embedded migrations whose version is not among the applied ones, oldest first.
+fn pending(embedded: &[(&str, &str)], applied: &HashSet<String>) -> Vec<String> {
+  embedded.iter()
+    .filter(|(version, _)| !applied.contains(*version))
+    .map(|(_, name)| (*name).to_owned())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  speculate! {
+    it "lists the embedded migrations that were not applied" {
+      assert!(MIGRATIONS.contains(&("20190507023347", "2019-05-07-023347_create_tokens")));
+
+      let applied = MIGRATIONS.iter().take(2).map(|(version, _)| (*version).to_owned()).collect();
+      assert_eq!(pending(MIGRATIONS, &applied), MIGRATIONS[2..].iter().map(|(_, name)| (*name).to_owned()).collect::<Vec<_>>());
+      assert_eq!(pending(MIGRATIONS, &HashSet::new()).len(), MIGRATIONS.len());
+    }
+  }
 }
@@ -1,3 +1,62 @@
+table! {
+    use diesel::sql_types::*;
+
+    auth_events (id) {
+        id -> Uuid,
+        jti -> Nullable<Uuid>,
+        user_id -> Nullable<Uuid>,
+        decision -> Varchar,
+        reason -> Nullable<Varchar>,
+        audiences -> Array<Text>,
+        remote_addr -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    authorization_rules (id) {
+        id -> Uuid,
+        subject_kind -> Varchar,
+        subject_name -> Varchar,
+        verbs -> Array<Text>,
+        api_groups -> Array<Text>,
+        resources -> Array<Text>,
+        namespaces -> Array<Text>,
+        non_resource_urls -> Array<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    groups (id) {
+        id -> Uuid,
+        name -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    signing_keys (id) {
+        id -> Uuid,
+        kid -> Varchar,
+        algorithm -> Varchar,
+        public_key -> Text,
+        private_key -> Nullable<Text>,
+        active -> Bool,
+        retire_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
 
@@ -8,5 +67,43 @@ table! {
         expires_at -> Timestamp,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        revoked_reason -> Nullable<Varchar>,
     }
 }
+
+table! {
+    use diesel::sql_types::*;
+
+    user_groups (user_id, group_id) {
+        user_id -> Uuid,
+        group_id -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    users (id) {
+        id -> Uuid,
+        username -> Varchar,
+        extra -> Jsonb,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+joinable!(tokens -> users (user_id));
+joinable!(user_groups -> groups (group_id));
+joinable!(user_groups -> users (user_id));
+
+allow_tables_to_appear_in_same_query!(
+    auth_events,
+    authorization_rules,
+    groups,
+    signing_keys,
+    tokens,
+    user_groups,
+    users,
+);
@@ -1 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+pub mod v1;
 pub mod v1beta1;
+
+/// A TokenReview in any of the supported `authentication.k8s.io` API versions.
+/// The variant is chosen by the `apiVersion` of the incoming object and is preserved when serializing,
+/// so a review is always answered in the version it was sent in.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum VersionedTokenReview {
+  V1(v1::TokenReview),
+  V1beta1(v1beta1::TokenReview)
+}
+
+impl VersionedTokenReview {
+  /// The spec of the wrapped TokenReview.
+  pub fn spec(&self) -> &v1::TokenReviewSpec {
+    match self {
+      VersionedTokenReview::V1(review)      => &review.spec,
+      VersionedTokenReview::V1beta1(review) => &review.spec
+    }
+  }
+
+  /// Replaces the status of the wrapped TokenReview.
+  pub fn set_status(&mut self, status: Option<v1::TokenReviewStatus>) {
+    match self {
+      VersionedTokenReview::V1(review)      => review.status = status,
+      VersionedTokenReview::V1beta1(review) => review.status = status
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  speculate! {
+    it "deserializes a v1 TokenReview" {
+      let json = r#"{"apiVersion": "authentication.k8s.io/v1", "kind": "TokenReview", "spec": {"token": "kitty"}}"#;
+      let review: VersionedTokenReview = serde_json::from_str(&json).unwrap();
+      assert!(match review { VersionedTokenReview::V1(_) => true, _ => false });
+    }
+
+    it "deserializes a v1beta1 TokenReview" {
+      let json = r#"{"apiVersion": "authentication.k8s.io/v1beta1", "kind": "TokenReview", "spec": {"token": "kitty"}}"#;
+      let review: VersionedTokenReview = serde_json::from_str(&json).unwrap();
+      assert!(match review { VersionedTokenReview::V1beta1(_) => true, _ => false });
+    }
+
+    it "replies in the version of the request" {
+      let json = r#"{"apiVersion": "authentication.k8s.io/v1beta1", "kind": "TokenReview", "spec": {"token": "kitty"}}"#;
+      let mut review: VersionedTokenReview = serde_json::from_str(&json).unwrap();
+      review.set_status(v1::TokenReviewStatus::denied());
+
+      let value = serde_json::to_value(&review).unwrap();
+      assert_eq!(value["apiVersion"], "authentication.k8s.io/v1beta1");
+      assert_eq!(value["status"]["authenticated"], false);
+    }
+
+    #[should_panic]
+    it "panics on an unsupported `apiVersion`" {
+      let json = r#"{"apiVersion": "authentication.k8s.io/v2", "kind": "TokenReview", "spec": {"token": "kitty"}}"#;
+      let _: VersionedTokenReview = serde_json::from_str(&json).unwrap();
+    }
+  }
+}
@@ -0,0 +1,11 @@
+mod token_review;
+pub use token_review::TokenReview;
+
+mod token_review_spec;
+pub use token_review_spec::TokenReviewSpec;
+
+mod token_review_status;
+pub use token_review_status::TokenReviewStatus;
+
+mod user_info;
+pub use user_info::UserInfo;
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::token;
+
+#[derive(Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct TokenReviewSpec {
+  /// Audiences is a list of the identifiers that the resource server presented with the token identifies as.
+  /// Audience-aware token authenticators will verify that the token was intended for at least one of the audiences
+  /// in this list. If no audiences are provided, the audience will default to the audience of the Kubernetes apiserver.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub audiences: Option<Vec<String>>,
+
+  /// Token is the opaque bearer token.
+  pub token: String
+}
+
+/// Never prints the token itself, only its fingerprint, so reviews can be logged safely.
+impl fmt::Debug for TokenReviewSpec {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("TokenReviewSpec")
+      .field("audiences", &self.audiences)
+      .field("token", &format_args!("<redacted {}>", token::fingerprint(&self.token)))
+      .finish()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  speculate! {
+    it "redacts the token when debug formatted" {
+      let spec = TokenReviewSpec { token: "kitty".into(), ..Default::default() };
+      let debug = format!("{:?}", spec);
+      assert!(!debug.contains("kitty"));
+      assert!(debug.contains(&token::fingerprint("kitty")));
+    }
+  }
+}
@@ -21,10 +21,28 @@ pub struct TokenReviewStatus {
 }
 
 impl TokenReviewStatus {
+  pub fn authenticated(user: super::UserInfo, audiences: Vec<String>) -> Option<TokenReviewStatus> {
+    Some(TokenReviewStatus {
+      audiences: if audiences.is_empty() { None } else { Some(audiences) },
+      authenticated: Some(true),
+      user: Some(user),
+      ..Default::default()
+    })
+  }
+
   pub fn denied() -> Option<TokenReviewStatus> {
     Some(TokenReviewStatus {
       authenticated: Some(false),
       ..Default::default()
     })
   }
+
+  pub fn errored<S>(message: S) -> Option<TokenReviewStatus>
+    where S: Into<String> {
+    Some(TokenReviewStatus {
+      authenticated: Some(false),
+      error: Some(message.into()),
+      ..Default::default()
+    })
+  }
 }
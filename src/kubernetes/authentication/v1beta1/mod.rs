@@ -1,11 +1,5 @@
 mod token_review;
 pub use token_review::TokenReview;
 
-mod token_review_spec;
-pub use token_review_spec::TokenReviewSpec;
-
-mod token_review_status;
-pub use token_review_status::TokenReviewStatus;
-
-mod user_info;
-pub use user_info::UserInfo;
+// The v1beta1 schema is identical to v1 for everything but the envelope.
+pub use super::v1::{TokenReviewSpec, TokenReviewStatus, UserInfo};
@@ -1,6 +0,0 @@
-use serde::{Deserialize, Serialize};
-
-#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
-pub struct TokenReviewSpec {
-  pub token: String
-}
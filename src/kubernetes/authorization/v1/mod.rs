@@ -0,0 +1,14 @@
+mod subject_access_review;
+pub use subject_access_review::SubjectAccessReview;
+
+mod subject_access_review_spec;
+pub use subject_access_review_spec::SubjectAccessReviewSpec;
+
+mod subject_access_review_status;
+pub use subject_access_review_status::SubjectAccessReviewStatus;
+
+mod resource_attributes;
+pub use resource_attributes::ResourceAttributes;
+
+mod non_resource_attributes;
+pub use non_resource_attributes::NonResourceAttributes;
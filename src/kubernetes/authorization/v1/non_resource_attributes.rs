@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct NonResourceAttributes {
+  /// Path is the URL path of the request.
+  pub path: Option<String>,
+
+  /// Verb is the standard HTTP verb.
+  pub verb: Option<String>
+}
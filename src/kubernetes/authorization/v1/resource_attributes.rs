@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ResourceAttributes {
+  /// Group is the API Group of the Resource. "*" means all.
+  pub group: Option<String>,
+
+  /// Name is the name of the resource being requested for a "get" or deleted for a "delete". "" (empty) means all.
+  pub name: Option<String>,
+
+  /// Namespace is the namespace of the action being requested. "" (empty) is defaulted for LocalSubjectAccessReviews,
+  /// "" (empty) is empty for cluster-scoped resources.
+  pub namespace: Option<String>,
+
+  /// Resource is one of the existing resource types. "*" means all.
+  pub resource: Option<String>,
+
+  /// Subresource is one of the existing resource types. "" means none.
+  pub subresource: Option<String>,
+
+  /// Verb is a kubernetes resource API verb, like: get, list, watch, create, update, delete, proxy. "*" means all.
+  pub verb: Option<String>,
+
+  /// Version is the API Version of the Resource. "*" means all.
+  pub version: Option<String>
+}
@@ -0,0 +1,80 @@
+use crate::kubernetes::Resource;
+
+/// SubjectAccessReview checks whether or not a user or group can perform an action.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubjectAccessReview {
+  pub spec:   super::SubjectAccessReviewSpec,
+  pub status: Option<super::SubjectAccessReviewStatus>
+}
+
+impl Resource for SubjectAccessReview {
+  fn api_version() -> &'static str {
+    "authorization.k8s.io/v1"
+  }
+
+  fn kind() -> &'static str {
+    "SubjectAccessReview"
+  }
+
+  fn version() -> &'static str {
+    "v1"
+  }
+}
+
+review_serde!(SubjectAccessReview);
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  speculate! {
+    it "deserializes a valid SubjectAccessReview" {
+      let json = r#"
+        {
+          "apiVersion": "authorization.k8s.io/v1",
+          "kind": "SubjectAccessReview",
+          "spec": {
+            "resourceAttributes": {
+              "namespace": "kittensandponies",
+              "verb": "get",
+              "group": "unicorn.example.org",
+              "resource": "pods"
+            },
+            "user": "jane",
+            "groups": ["group1", "group2"]
+          }
+        }"#;
+      let review: SubjectAccessReview = serde_json::from_str(&json).unwrap();
+      assert_eq!(review.spec.user, Some("jane".to_owned()));
+      assert_eq!(review.spec.resource_attributes.unwrap().namespace, Some("kittensandponies".to_owned()));
+    }
+
+    it "ignores `metadata`" {
+      let json = r#"
+        {
+          "apiVersion": "authorization.k8s.io/v1",
+          "kind": "SubjectAccessReview",
+          "metadata": { "creationTimestamp": null },
+          "spec": {
+            "user": "jane"
+          }
+        }"#;
+      let review: SubjectAccessReview = serde_json::from_str(&json).unwrap();
+      assert_eq!(review.spec.user, Some("jane".to_owned()));
+    }
+
+    #[should_panic]
+    it "panics when `kind` is a TokenReview" {
+      let json = r#"
+        {
+          "apiVersion": "authorization.k8s.io/v1",
+          "kind": "TokenReview",
+          "spec": {
+            "user": "jane"
+          }
+        }"#;
+      let _: SubjectAccessReview = serde_json::from_str(&json).unwrap();
+    }
+  }
+}
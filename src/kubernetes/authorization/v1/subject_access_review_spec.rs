@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubjectAccessReviewSpec {
+  /// Extra corresponds to the user.Info.GetExtra() method from the authenticator.
+  pub extra: Option<std::collections::BTreeMap<String, Vec<String>>>,
+
+  /// Groups is the groups you're testing for.
+  pub groups: Option<Vec<String>>,
+
+  /// NonResourceAttributes describes information for a non-resource access request.
+  pub non_resource_attributes: Option<super::NonResourceAttributes>,
+
+  /// ResourceAttributes describes information for a resource access request.
+  pub resource_attributes: Option<super::ResourceAttributes>,
+
+  /// UID information about the requesting user.
+  pub uid: Option<String>,
+
+  /// User is the user you're testing for.
+  pub user: Option<String>
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubjectAccessReviewStatus {
+  /// Allowed is required. True if the action would be allowed, false otherwise.
+  pub allowed: bool,
+
+  /// Denied is optional. True if the action would be denied, otherwise false.
+  /// If both allowed is false and denied is false, then the authorizer has no opinion on whether to authorize the action.
+  /// Denied may not be true if Allowed is true.
+  pub denied: Option<bool>,
+
+  /// EvaluationError is an indication that some error occurred during the authorization check.
+  /// It is entirely possible to get an error and be able to continue determine authorization status in spite of it.
+  pub evaluation_error: Option<String>,
+
+  /// Reason is optional. It indicates why a request was allowed or denied.
+  pub reason: Option<String>
+}
+
+impl SubjectAccessReviewStatus {
+  pub fn allowed<S>(reason: S) -> Option<SubjectAccessReviewStatus>
+    where S: Into<String> {
+    Some(SubjectAccessReviewStatus {
+      allowed: true,
+      reason: Some(reason.into()),
+      ..Default::default()
+    })
+  }
+
+  /// Neither allowed nor denied, leaving the decision to the next authorizer configured in the API server.
+  /// `denied` is only for explicit deny rules, which would stop the API server from asking other authorizers.
+  pub fn no_opinion<S>(reason: S) -> Option<SubjectAccessReviewStatus>
+    where S: Into<String> {
+    Some(SubjectAccessReviewStatus {
+      allowed: false,
+      reason: Some(reason.into()),
+      ..Default::default()
+    })
+  }
+
+  pub fn errored<S>(message: S) -> Option<SubjectAccessReviewStatus>
+    where S: Into<String> {
+    Some(SubjectAccessReviewStatus {
+      allowed: false,
+      evaluation_error: Some(message.into()),
+      ..Default::default()
+    })
+  }
+}
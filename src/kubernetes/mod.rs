@@ -1,4 +1,128 @@
+/// Implements `Deserialize` and `Serialize` for a review object: a `spec` and an optional `status` in an envelope whose
+/// `apiVersion` and `kind` must match the type's [`Resource`] implementation.
+/// `metadata`, which the API server sends along (usually empty), is accepted and ignored.
+macro_rules! review_serde {
+  ($review:ident) => {
+    impl<'de> serde::Deserialize<'de> for $review {
+      fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: serde::Deserializer<'de> {
+        use serde::de::{self, Visitor};
+        use $crate::kubernetes::Resource;
+
+        // Field names (Used for error messages)
+        const FIELDS: &'static [&'static str] = &["apiVersion", "kind", "metadata", "spec", "status"];
+
+        // Fields that are permitted for review objects
+        enum Field {
+          ApiVersion,
+          Kind,
+          Metadata,
+          Spec,
+          Status
+        }
+
+        impl<'de> serde::Deserialize<'de> for Field {
+          fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: serde::Deserializer<'de> {
+            struct FieldVisitor;
+
+            impl<'de> Visitor<'de> for FieldVisitor {
+              type Value = Field;
+
+              fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "Field Identifier")
+              }
+
+              /// Check each key & ensure nothing wacky gets passed
+              fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> where E: de::Error {
+                match value {
+                  "apiVersion" => Ok(Field::ApiVersion),
+                  "kind"       => Ok(Field::Kind),
+                  "metadata"   => Ok(Field::Metadata),
+                  "spec"       => Ok(Field::Spec),
+                  "status"     => Ok(Field::Status),
+                  _            => Err(de::Error::unknown_field(value, FIELDS))
+                }
+              }
+            }
+
+            deserializer.deserialize_identifier(FieldVisitor)
+          }
+        }
+
+        struct ReviewVisitor;
+        impl<'de> Visitor<'de> for ReviewVisitor {
+          type Value = $review;
+
+          fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str(stringify!($review))
+          }
+
+          fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error> where A: de::MapAccess<'de> {
+            let mut value_spec    = None;
+            let mut value_status  = None;
+            let mut value_version = None;
+            let mut value_kind    = None;
+
+            while let Some(key) = de::MapAccess::next_key::<Field>(&mut map)? {
+              match key {
+                Field::ApiVersion => {
+                  // Ensures that the api version matches
+                  let version: String = de::MapAccess::next_value(&mut map)?;
+                  if version != <Self::Value as Resource>::api_version() {
+                    return Err(de::Error::invalid_value(de::Unexpected::Str(&version), &<Self::Value as Resource>::api_version()));
+                  }
+                  value_version = Some(version);
+                },
+                Field::Kind => {
+                  // Ensures this is the expected kind of object
+                  let kind: String = de::MapAccess::next_value(&mut map)?;
+                  if kind != <Self::Value as Resource>::kind() {
+                    return Err(de::Error::invalid_value(de::Unexpected::Str(&kind), &<Self::Value as Resource>::kind()));
+                  }
+                  value_kind = Some(kind);
+                },
+                Field::Metadata => {
+                  de::MapAccess::next_value::<de::IgnoredAny>(&mut map)?;
+                },
+                Field::Spec   => value_spec   = Some(de::MapAccess::next_value(&mut map)?),
+                Field::Status => value_status = de::MapAccess::next_value(&mut map)?
+              }
+            }
+
+            value_version.ok_or_else(|| de::Error::missing_field("apiVersion"))?;
+            value_kind.ok_or_else(|| de::Error::missing_field("kind"))?;
+
+            Ok($review {
+              spec: value_spec.ok_or_else(|| de::Error::missing_field("spec"))?,
+              status: value_status
+            })
+          }
+        }
+
+        deserializer.deserialize_struct(stringify!($review), FIELDS, ReviewVisitor)
+      }
+    }
+
+    impl serde::Serialize for $review {
+      fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
+        use serde::ser::SerializeStruct;
+        use $crate::kubernetes::Resource;
+
+        let mut state = serializer.serialize_struct(stringify!($review), 3 + self.status.as_ref().map_or(0, |_| 1))?;
+
+        SerializeStruct::serialize_field(&mut state, "apiVersion", <Self as Resource>::api_version())?;
+        SerializeStruct::serialize_field(&mut state, "kind", <Self as Resource>::kind())?;
+        SerializeStruct::serialize_field(&mut state, "spec", &self.spec)?;
+        if let Some(value) = &self.status {
+          SerializeStruct::serialize_field(&mut state, "status", value)?;
+        }
+        SerializeStruct::end(state)
+      }
+    }
+  };
+}
+
 pub mod authentication;
+pub mod authorization;
 
 /// A trait applied to all Kubernetes resources.
 pub trait Resource {
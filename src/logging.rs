@@ -1,22 +1,79 @@
-use failure::Fallible;
+use failure::{Fallible, format_err};
+use log::LevelFilter;
+use serde_json::json;
+use std::str::FromStr;
+
+use crate::settings::{LogFormat, Logging};
+
+/// Noisy targets quietened unless overridden in the settings.
+const DEFAULT_TARGETS: &[(&str, LevelFilter)] = &[
+  ("tokio_reactor", LevelFilter::Warn),
+  ("actix_web::server::server", LevelFilter::Warn)
+];
 
 /// Initializes the logging system.
-pub fn init() -> Fallible<()> {
-  fern::Dispatch::new()
-    .format(|out, message, record| {
-      out.finish(format_args!(
+///
+/// # Arguments
+/// * `settings` - Logging settings to use.
+pub fn init(settings: &Logging) -> Fallible<()> {
+  let format = settings.format;
+
+  let mut dispatch = fern::Dispatch::new()
+    .format(move |out, message, record| match format {
+      LogFormat::Text => out.finish(format_args!(
         "{}[{}][{}] {}",
         chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
         record.target(),
         record.level(),
         message
+      )),
+      LogFormat::Json => out.finish(format_args!(
+        "{}",
+        json!({
+          "timestamp": chrono::Utc::now().to_rfc3339(),
+          "level": record.level().to_string(),
+          "target": record.target(),
+          "message": message.to_string()
+        })
       ))
-    }).level(log::LevelFilter::Debug)
-    .level_for("tokio_reactor", log::LevelFilter::Warn)
-    .level_for("actix_web::server::server", log::LevelFilter::Warn)
-    .chain(std::io::stdout())
-    .chain(fern::log_file("output.log")?)
-    .apply()?;
+    })
+    .level(parse_level(&settings.level)?);
+
+  for (target, level) in DEFAULT_TARGETS {
+    if !settings.targets.contains_key(*target) {
+      dispatch = dispatch.level_for(*target, *level);
+    }
+  }
+  for (target, level) in &settings.targets {
+    dispatch = dispatch.level_for(target.to_owned(), parse_level(level)?);
+  }
+
+  if settings.stdout {
+    dispatch = dispatch.chain(std::io::stdout());
+  }
+  if let Some(ref path) = settings.file {
+    dispatch = dispatch.chain(fern::log_file(path)?);
+  }
 
+  dispatch.apply()?;
   Ok(())
 }
+
+/// Parses a level name, case insensitively.
+fn parse_level(level: &str) -> Fallible<LevelFilter> {
+  LevelFilter::from_str(level).map_err(|_| format_err!("invalid log level {:?}", level))
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  speculate! {
+    it "parses level names" {
+      assert_eq!(parse_level("debug").unwrap(), LevelFilter::Debug);
+      assert_eq!(parse_level("WARN").unwrap(), LevelFilter::Warn);
+      assert!(parse_level("loud").is_err());
+    }
+  }
+}
@@ -13,40 +13,30 @@ extern crate diesel_migrations;
 #[macro_use]
 extern crate diesel;
 
-// #[macro_use]
-// extern crate validator_derive;
+#[macro_use]
+extern crate validator_derive;
 
 use failure::Fallible;
-use clap::{App, Arg};
 
 mod db;
+mod cli;
+mod token;
 mod models;
 mod server;
 mod logging;
+mod signing;
 mod settings;
 mod kubernetes;
+mod authenticators;
 
 use settings::Settings;
-use server::Server;
 
 use diesel_migrations::embed_migrations;
 
 embed_migrations!("./migrations");
 
 fn main() -> Fallible<()> {
-  logging::init()?;
-
-  let arguments = App::new("Heimdallr")
-    .about("API Authentication Service")
-    .version(crate_version!())
-    .arg(
-      Arg::with_name("config")
-        .long("config")
-        .short("c")
-        .value_name("FILE")
-        .help("Sets a custom config file")
-        .takes_value(true)
-    ).get_matches();
+  let arguments = cli::app().get_matches();
 
   // Figure out what config file to load
   let cwd = ::std::env::current_dir()?;
@@ -54,7 +44,9 @@ fn main() -> Fallible<()> {
   let config_file    = arguments.value_of("config").unwrap_or(&default_config);
 
   let settings = Settings::new(config_file)?;
-  let server   = Server::from_settings(&settings)?;
-  server.start()?;
-  Ok(())
+
+  // Logging is configured by the settings, so nothing can be logged before this point
+  logging::init(&settings.logging)?;
+
+  cli::run(&arguments, &settings)
 }
@@ -1,3 +1,20 @@
 mod token;
 pub use token::Token;
 pub use token::Claims;
+
+mod user;
+pub use user::User;
+
+mod group;
+pub use group::Group;
+pub use group::UserGroup;
+
+mod authorization_rule;
+pub use authorization_rule::AuthorizationRule;
+
+mod signing_key;
+pub use signing_key::StoredSigningKey;
+
+mod auth_event;
+pub use auth_event::AuthEvent;
+pub use auth_event::NewAuthEvent;
@@ -0,0 +1,84 @@
+use diesel::{RunQueryDsl, QueryDsl, ExpressionMethods};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::auth_events;
+use crate::server::HttpError;
+
+/// A recorded TokenReview decision.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Identifiable, Queryable)]
+#[table_name="auth_events"]
+pub struct AuthEvent {
+  pub id: Uuid,
+
+  /// `jti` of the reviewed token, when it could be decoded.
+  pub jti: Option<Uuid>,
+
+  /// Owner of the reviewed token, when it is known.
+  pub user_id: Option<Uuid>,
+
+  /// authenticated, denied or error.
+  pub decision: String,
+  pub reason: Option<String>,
+
+  /// Audiences requested by the TokenReview.
+  pub audiences: Vec<String>,
+
+  /// Address of the client that sent the TokenReview.
+  pub remote_addr: Option<String>,
+  pub created_at: NaiveDateTime
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name="auth_events"]
+pub struct NewAuthEvent {
+  pub jti: Option<Uuid>,
+  pub user_id: Option<Uuid>,
+  pub decision: String,
+  pub reason: Option<String>,
+  pub audiences: Vec<String>,
+  pub remote_addr: Option<String>
+}
+
+impl NewAuthEvent {
+  /// Stores several events with a single insert.
+  ///
+  /// # Arguments
+  /// * `events` - Events to store.
+  /// * `conn`   - Database connection to use.
+  pub fn insert_all(events: &[NewAuthEvent], conn: &diesel::pg::PgConnection) -> Result<(), HttpError> {
+    use crate::db::auth_events::dsl::auth_events;
+
+    diesel::insert_into(auth_events)
+      .values(events)
+      .execute(conn)?;
+    Ok(())
+  }
+}
+
+impl AuthEvent {
+  /// Lists events, newest first.
+  ///
+  /// # Arguments
+  /// * `owner` - Only list events of this user.
+  /// * `since` - Only list events recorded at or after this time.
+  /// * `limit` - Maximum number of events to return.
+  /// * `conn`  - Database connection to use.
+  pub fn list(owner: Option<Uuid>, since: Option<NaiveDateTime>, limit: i64, conn: &diesel::pg::PgConnection) -> Result<Vec<AuthEvent>, HttpError> {
+    use crate::db::auth_events::dsl::*;
+
+    let mut query = auth_events.into_boxed();
+    if let Some(owner) = owner {
+      query = query.filter(user_id.eq(owner));
+    }
+    if let Some(since) = since {
+      query = query.filter(created_at.ge(since));
+    }
+
+    Ok(query
+      .order(created_at.desc())
+      .limit(limit)
+      .load(conn)?)
+  }
+}
@@ -0,0 +1,163 @@
+use diesel::{RunQueryDsl, QueryDsl, ExpressionMethods, BoolExpressionMethods};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::authorization_rules;
+use crate::server::HttpError;
+use crate::kubernetes::authorization::v1::{SubjectAccessReviewSpec, ResourceAttributes, NonResourceAttributes};
+
+/// Matches any value in a rule list.
+const WILDCARD: &str = "*";
+
+/// A rule granting a user or group access to resources or non-resource URLs.
+/// Empty `namespaces` grant access in every namespace; every other list must match explicitly (or contain `*`).
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Identifiable, Queryable)]
+#[table_name="authorization_rules"]
+pub struct AuthorizationRule {
+  pub id: Uuid,
+  pub subject_kind: String,
+  pub subject_name: String,
+  pub verbs: Vec<String>,
+  pub api_groups: Vec<String>,
+  pub resources: Vec<String>,
+  pub namespaces: Vec<String>,
+  pub non_resource_urls: Vec<String>,
+  pub created_at: NaiveDateTime,
+  pub updated_at: NaiveDateTime
+}
+
+impl AuthorizationRule {
+  /// Loads every rule that applies to a user or any of their groups.
+  ///
+  /// # Arguments
+  /// * `user`   - Username to load rules for.
+  /// * `groups` - Groups the user is a member of.
+  /// * `conn`   - Database connection to use.
+  pub fn for_subject(user: &str, groups: &[String], conn: &diesel::pg::PgConnection) -> Result<Vec<AuthorizationRule>, HttpError> {
+    use crate::db::authorization_rules::dsl::*;
+
+    Ok(authorization_rules
+      .filter(subject_kind.eq("User").and(subject_name.eq(user)))
+      .or_filter(subject_kind.eq("Group").and(subject_name.eq_any(groups)))
+      .load(conn)?)
+  }
+
+  /// Checks whether this rule permits the access described by a SubjectAccessReview.
+  ///
+  /// # Arguments
+  /// * `spec` - Access being requested.
+  pub fn allows(&self, spec: &SubjectAccessReviewSpec) -> bool {
+    match (&spec.resource_attributes, &spec.non_resource_attributes) {
+      (Some(attributes), _)    => self.allows_resource(attributes),
+      (None, Some(attributes)) => self.allows_non_resource(attributes),
+      (None, None)             => false
+    }
+  }
+
+  /// A short description of the rule, used as the review reason.
+  pub fn describe(&self) -> String {
+    format!("allowed by rule {} for {} {:?}", self.id, self.subject_kind, self.subject_name)
+  }
+
+  fn allows_resource(&self, attributes: &ResourceAttributes) -> bool {
+    let resource = match (&attributes.resource, &attributes.subresource) {
+      (Some(resource), Some(subresource)) if !subresource.is_empty() => format!("{}/{}", resource, subresource),
+      (Some(resource), _) => resource.to_owned(),
+      (None, _)           => return false
+    };
+
+    let namespace_allowed = self.namespaces.is_empty() || matches(&self.namespaces, attributes.namespace.as_ref().map_or("", String::as_str));
+
+    matches(&self.verbs, attributes.verb.as_ref().map_or("", String::as_str))
+      && matches(&self.api_groups, attributes.group.as_ref().map_or("", String::as_str))
+      && matches(&self.resources, &resource)
+      && namespace_allowed
+  }
+
+  fn allows_non_resource(&self, attributes: &NonResourceAttributes) -> bool {
+    let path = match attributes.path {
+      Some(ref path) => path,
+      None           => return false
+    };
+
+    let path_allowed = self.non_resource_urls.iter().any(|url| {
+      url == WILDCARD || url == path || (url.ends_with(WILDCARD) && path.starts_with(&url[..url.len() - 1]))
+    });
+
+    matches(&self.verbs, attributes.verb.as_ref().map_or("", String::as_str)) && path_allowed
+  }
+}
+
+/// Checks whether a rule list contains a value or the wildcard.
+fn matches(list: &[String], value: &str) -> bool {
+  list.iter().any(|item| item == WILDCARD || item == value)
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  fn rule() -> AuthorizationRule {
+    AuthorizationRule {
+      id: Uuid::new_v4(),
+      subject_kind: "Group".into(),
+      subject_name: "developers".into(),
+      verbs: vec!["get".into(), "list".into()],
+      api_groups: vec!["".into()],
+      resources: vec!["pods".into(), "pods/log".into()],
+      namespaces: vec!["default".into()],
+      non_resource_urls: vec!["/healthz".into(), "/apis/*".into()],
+      created_at: chrono::Utc::now().naive_utc(),
+      updated_at: chrono::Utc::now().naive_utc()
+    }
+  }
+
+  fn resource(verb: &str, resource: &str, subresource: Option<&str>, namespace: &str) -> SubjectAccessReviewSpec {
+    SubjectAccessReviewSpec {
+      resource_attributes: Some(ResourceAttributes {
+        verb: Some(verb.into()),
+        group: Some("".into()),
+        resource: Some(resource.into()),
+        subresource: subresource.map(Into::into),
+        namespace: Some(namespace.into()),
+        ..Default::default()
+      }),
+      ..Default::default()
+    }
+  }
+
+  fn non_resource(verb: &str, path: &str) -> SubjectAccessReviewSpec {
+    SubjectAccessReviewSpec {
+      non_resource_attributes: Some(NonResourceAttributes { verb: Some(verb.into()), path: Some(path.into()) }),
+      ..Default::default()
+    }
+  }
+
+  speculate! {
+    it "allows matching resource requests" {
+      assert!(rule().allows(&resource("get", "pods", None, "default")));
+      assert!(rule().allows(&resource("list", "pods", Some("log"), "default")));
+    }
+
+    it "denies other verbs, resources and namespaces" {
+      assert!(!rule().allows(&resource("delete", "pods", None, "default")));
+      assert!(!rule().allows(&resource("get", "secrets", None, "default")));
+      assert!(!rule().allows(&resource("get", "pods", Some("exec"), "default")));
+      assert!(!rule().allows(&resource("get", "pods", None, "kube-system")));
+    }
+
+    it "allows any namespace when none are listed" {
+      let mut rule = rule();
+      rule.namespaces = vec![];
+      assert!(rule.allows(&resource("get", "pods", None, "kube-system")));
+    }
+
+    it "matches non-resource URLs exactly or by prefix" {
+      assert!(rule().allows(&non_resource("get", "/healthz")));
+      assert!(rule().allows(&non_resource("get", "/apis/apps/v1")));
+      assert!(!rule().allows(&non_resource("get", "/metrics")));
+    }
+  }
+}
@@ -0,0 +1,67 @@
+use diesel::{RunQueryDsl, QueryDsl, ExpressionMethods};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::{groups, user_groups};
+use crate::server::HttpError;
+use super::User;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Identifiable, Queryable)]
+#[table_name="groups"]
+pub struct Group {
+  pub id: Uuid,
+  pub name: String,
+  pub created_at: NaiveDateTime,
+  pub updated_at: NaiveDateTime
+}
+
+impl Group {
+  /// Finds a group by name, creating it if it does not exist yet.
+  ///
+  /// # Arguments
+  /// * `group_name` - Unique name of the group.
+  /// * `conn`       - Database connection to use.
+  pub fn find_or_create(group_name: &str, conn: &diesel::pg::PgConnection) -> Result<Group, HttpError> {
+    use crate::db::groups::dsl::*;
+
+    diesel::insert_into(groups)
+      .values(name.eq(group_name))
+      .on_conflict(name)
+      .do_nothing()
+      .execute(conn)?;
+
+    Ok(groups.filter(name.eq(group_name)).first(conn)?)
+  }
+}
+
+/// Membership of a user in a group.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Identifiable, Queryable, Associations)]
+#[table_name="user_groups"]
+#[primary_key(user_id, group_id)]
+#[belongs_to(User)]
+#[belongs_to(Group)]
+pub struct UserGroup {
+  pub user_id: Uuid,
+  pub group_id: Uuid,
+  pub created_at: NaiveDateTime
+}
+
+impl UserGroup {
+  /// Adds a user to a group, doing nothing if they already are a member.
+  ///
+  /// # Arguments
+  /// * `user`  - User to add.
+  /// * `group` - Group to add the user to.
+  /// * `conn`  - Database connection to use.
+  pub fn add(user: &User, group: &Group, conn: &diesel::pg::PgConnection) -> Result<(), HttpError> {
+    use crate::db::user_groups::dsl::*;
+
+    diesel::insert_into(user_groups)
+      .values((user_id.eq(user.id), group_id.eq(group.id)))
+      .on_conflict_do_nothing()
+      .execute(conn)?;
+
+    Ok(())
+  }
+}
@@ -0,0 +1,38 @@
+use diesel::{RunQueryDsl, QueryDsl, ExpressionMethods, BoolExpressionMethods};
+use chrono::{NaiveDateTime, Utc};
+
+use crate::db::signing_keys;
+use crate::server::HttpError;
+
+/// A token signing key stored in the database, loaded into the key ring next to the configured keys.
+#[derive(Clone, Debug, PartialEq, Identifiable, Queryable)]
+#[table_name="signing_keys"]
+pub struct StoredSigningKey {
+  pub id: uuid::Uuid,
+  pub kid: String,
+  pub algorithm: String,
+  pub public_key: String,
+
+  /// PEM private key encrypted with the `StorageKey`, never stored in the clear.
+  pub private_key: Option<String>,
+
+  pub active: bool,
+  pub retire_at: Option<NaiveDateTime>,
+  pub created_at: NaiveDateTime,
+  pub updated_at: NaiveDateTime
+}
+
+impl StoredSigningKey {
+  /// Loads every key that has not retired yet.
+  ///
+  /// # Arguments
+  /// * `conn` - Database connection to use.
+  pub fn unretired(conn: &diesel::pg::PgConnection) -> Result<Vec<StoredSigningKey>, HttpError> {
+    use crate::db::signing_keys::dsl::*;
+
+    Ok(signing_keys
+      .filter(retire_at.is_null().or(retire_at.gt(Utc::now().naive_utc())))
+      .order(created_at.asc())
+      .load(conn)?)
+  }
+}
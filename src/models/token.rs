@@ -1,11 +1,11 @@
 use jsonwebtoken::{decode as jwt_decode, Header, Algorithm, Validation};
-use diesel::{RunQueryDsl, ExpressionMethods, pg::Pg, sql_types::Jsonb};
+use diesel::{RunQueryDsl, QueryDsl, ExpressionMethods, pg::Pg, sql_types::Jsonb};
 use chrono::{Local, NaiveDateTime, Utc, Duration};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::db::tokens;
-use crate::server::HttpError;
+use crate::server::{DecisionCache, HttpError};
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Identifiable, Queryable, Insertable, AsChangeset)]
 #[table_name="tokens"]
@@ -13,7 +13,11 @@ pub struct Token {
   pub id: Uuid,
   pub user_id: Uuid,
   pub claims: Claims,
-  pub expires_at: NaiveDateTime
+  pub expires_at: NaiveDateTime,
+  pub created_at: NaiveDateTime,
+  pub updated_at: NaiveDateTime,
+  pub revoked_at: Option<NaiveDateTime>,
+  pub revoked_reason: Option<String>
 }
 
 #[derive(Clone, Debug, Insertable, AsChangeset)]
@@ -26,7 +30,16 @@ struct NewToken {
 }
 
 impl Token {
-  fn new(user_id: Uuid, scopes: Vec<String>, expires_at: NaiveDateTime, conn: &diesel::pg::PgConnection) -> Result<Token, HttpError> {
+  /// Creates and stores a new token for a user.
+  ///
+  /// # Arguments
+  /// * `user_id`    - User the token is issued to.
+  /// * `scopes`     - Scopes granted to the token.
+  /// * `audiences`  - Value of the `aud` claim.
+  /// * `issuer`     - Value of the `iss` claim.
+  /// * `expires_at` - When the token stops being valid.
+  /// * `conn`       - Database connection to use.
+  pub fn new(user_id: Uuid, scopes: Vec<String>, audiences: Vec<String>, issuer: &str, expires_at: NaiveDateTime, conn: &diesel::pg::PgConnection) -> Result<Token, HttpError> {
     use crate::db::tokens::dsl::tokens;
     let generated_id = Uuid::new_v4();
 
@@ -35,9 +48,13 @@ impl Token {
       expires_at,
       id: generated_id,
       claims: Claims {
+        sub: user_id.to_string(),
+        iss: issuer.to_owned(),
         user_id: Some(user_id),
         exp: expires_at.timestamp(),
         jti: generated_id,
+        scopes,
+        aud: audiences,
         ..Default::default()
       }
     };
@@ -47,6 +64,84 @@ impl Token {
       .get_result(conn)?)
   }
 
+  /// Finds a token that has neither expired nor been revoked.
+  ///
+  /// # Arguments
+  /// * `jti`  - Unique token identifier (`jti` claim).
+  /// * `conn` - Database connection to use.
+  pub fn find_active(jti: Uuid, conn: &diesel::pg::PgConnection) -> Result<Token, HttpError> {
+    use crate::db::tokens::dsl::*;
+
+    Ok(tokens
+      .filter(id.eq(jti))
+      .filter(expires_at.gt(Utc::now().naive_utc()))
+      .filter(revoked_at.is_null())
+      .first(conn)?)
+  }
+
+  /// Lists tokens, newest first.
+  ///
+  /// # Arguments
+  /// * `owner`    - Only list tokens of this user.
+  /// * `inactive` - Also list expired and revoked tokens.
+  /// * `conn`     - Database connection to use.
+  pub fn list(owner: Option<Uuid>, inactive: bool, conn: &diesel::pg::PgConnection) -> Result<Vec<Token>, HttpError> {
+    use crate::db::tokens::dsl::*;
+
+    let mut query = tokens.order(created_at.desc()).into_boxed();
+    if let Some(owner) = owner {
+      query = query.filter(user_id.eq(owner));
+    }
+    if !inactive {
+      query = query
+        .filter(expires_at.gt(Utc::now().naive_utc()))
+        .filter(revoked_at.is_null());
+    }
+
+    Ok(query.load(conn)?)
+  }
+
+  /// Revokes a single token.
+  /// Returns `HttpError::NotFound` if the token does not exist or was already revoked.
+  ///
+  /// # Arguments
+  /// * `token_id` - Token to revoke.
+  /// * `reason`   - Why the token is being revoked.
+  /// * `cache`    - Decision cache of this process to drop the token from, if any.
+  /// * `conn`     - Database connection to use.
+  pub fn revoke(token_id: Uuid, reason: Option<String>, cache: Option<&DecisionCache>, conn: &diesel::pg::PgConnection) -> Result<Token, HttpError> {
+    use crate::db::tokens::dsl::*;
+
+    let token: Token = diesel::update(tokens.filter(id.eq(token_id)).filter(revoked_at.is_null()))
+      .set((revoked_at.eq(Utc::now().naive_utc()), revoked_reason.eq(reason)))
+      .get_result(conn)?;
+
+    if let Some(cache) = cache {
+      cache.invalidate_token(token.id);
+    }
+    Ok(token)
+  }
+
+  /// Revokes every outstanding token belonging to a user, returning the revoked tokens.
+  ///
+  /// # Arguments
+  /// * `owner`  - User whose tokens should be revoked.
+  /// * `reason` - Why the tokens are being revoked.
+  /// * `cache`  - Decision cache of this process to drop the tokens from, if any.
+  /// * `conn`   - Database connection to use.
+  pub fn revoke_all_for_user(owner: Uuid, reason: Option<String>, cache: Option<&DecisionCache>, conn: &diesel::pg::PgConnection) -> Result<Vec<Token>, HttpError> {
+    use crate::db::tokens::dsl::*;
+
+    let revoked = diesel::update(tokens.filter(user_id.eq(owner)).filter(revoked_at.is_null()))
+      .set((revoked_at.eq(Utc::now().naive_utc()), revoked_reason.eq(reason)))
+      .get_results(conn)?;
+
+    if let Some(cache) = cache {
+      cache.invalidate_user(owner);
+    }
+    Ok(revoked)
+  }
+
   // fn upsert(self, conn: &diesel::pg::PgConnection) {
   //   use crate::db::tokens::dsl::*;
   //   diesel::insert_into(tokens)
@@ -68,7 +163,11 @@ pub struct Claims {
   pub nbf: i64,
   pub jti: Uuid,
   pub user_id: Option<Uuid>,
-  pub scopes: Vec<String>
+  pub scopes: Vec<String>,
+
+  /// Audiences the token is intended for, may be a single string or an array in the JWT.
+  #[serde(default, deserialize_with = "deserialize_audiences", skip_serializing_if = "Vec::is_empty")]
+  pub aud: Vec<String>
 }
 
 impl Default for Claims {
@@ -81,11 +180,47 @@ impl Default for Claims {
       nbf: Utc::now().timestamp(),
       jti: Uuid::new_v4(),
       user_id: None,
-      scopes: vec![]
+      scopes: vec![],
+      aud: vec![]
+    }
+  }
+}
+
+impl Claims {
+  /// Intersects the requested audiences with the token's `aud` claim.
+  /// Returns `None` when audiences were requested but none of them match, meaning the token must be denied.
+  ///
+  /// # Arguments
+  /// * `requested` - Audiences from the TokenReview spec.
+  pub fn matching_audiences(&self, requested: &[String]) -> Option<Vec<String>> {
+    if requested.is_empty() {
+      return Some(vec![]);
     }
+
+    let matching: Vec<String> = requested.iter()
+      .filter(|audience| self.aud.contains(audience))
+      .cloned()
+      .collect();
+
+    if matching.is_empty() { None } else { Some(matching) }
   }
 }
 
+/// Accepts `aud` as either a single string or an array of strings.
+fn deserialize_audiences<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error> where D: serde::Deserializer<'de> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum Audiences {
+    One(String),
+    Many(Vec<String>)
+  }
+
+  Ok(match Audiences::deserialize(deserializer)? {
+    Audiences::One(audience)  => vec![audience],
+    Audiences::Many(audiences) => audiences
+  })
+}
+
 impl diesel::deserialize::FromSql<Jsonb, Pg> for Claims {
   fn from_sql(bytes: Option<&[u8]>) -> diesel::deserialize::Result<Self> {
     let value = <serde_json::Value as diesel::deserialize::FromSql<Jsonb, Pg>>::from_sql(bytes)?;
@@ -99,3 +234,27 @@ impl diesel::serialize::ToSql<Jsonb, Pg> for Claims {
     <serde_json::Value as diesel::serialize::ToSql<Jsonb, Pg>>::to_sql(&value, out)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  speculate! {
+    it "accepts `aud` as a string or an array" {
+      let claims: Claims = serde_json::from_value(serde_json::json!({
+        "sub": "kitty", "iss": "heimdallr", "iat": 0, "exp": 0, "nbf": 0,
+        "jti": Uuid::new_v4(), "user_id": null, "scopes": [], "aud": "kubernetes"
+      })).unwrap();
+      assert_eq!(claims.aud, vec!["kubernetes".to_owned()]);
+    }
+
+    it "intersects requested audiences" {
+      let claims = Claims { aud: vec!["kubernetes".into(), "vault".into()], ..Default::default() };
+
+      assert_eq!(claims.matching_audiences(&[]), Some(vec![]));
+      assert_eq!(claims.matching_audiences(&["vault".into(), "consul".into()]), Some(vec!["vault".to_owned()]));
+      assert_eq!(claims.matching_audiences(&["consul".into()]), None);
+    }
+  }
+}
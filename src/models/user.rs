@@ -0,0 +1,109 @@
+use diesel::{RunQueryDsl, QueryDsl, ExpressionMethods};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::db::users;
+use crate::server::HttpError;
+use crate::kubernetes::authentication::v1::UserInfo;
+use super::Claims;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Identifiable, Queryable)]
+#[table_name="users"]
+pub struct User {
+  pub id: Uuid,
+  pub username: String,
+
+  /// Additional attributes returned as `UserInfo.extra`, a map of string to list of strings.
+  pub extra: serde_json::Value,
+  pub created_at: NaiveDateTime,
+  pub updated_at: NaiveDateTime
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name="users"]
+struct NewUser {
+  username: String,
+  extra: serde_json::Value
+}
+
+impl User {
+  /// Creates a new user.
+  ///
+  /// # Arguments
+  /// * `username` - Unique name of the user.
+  /// * `extra`    - Additional attributes returned as `UserInfo.extra`.
+  /// * `conn`     - Database connection to use.
+  pub fn create<S>(username: S, extra: BTreeMap<String, Vec<String>>, conn: &diesel::pg::PgConnection) -> Result<User, HttpError>
+    where S: Into<String> {
+    use crate::db::users::dsl::users;
+
+    let new_user = NewUser {
+      username: username.into(),
+      extra: serde_json::to_value(extra).map_err(|_| HttpError::InternalServerError)?
+    };
+
+    Ok(diesel::insert_into(users)
+      .values(&new_user)
+      .get_result(conn)?)
+  }
+
+  /// Finds a user by id.
+  ///
+  /// # Arguments
+  /// * `user_id` - User to find.
+  /// * `conn`    - Database connection to use.
+  pub fn find(user_id: Uuid, conn: &diesel::pg::PgConnection) -> Result<User, HttpError> {
+    use crate::db::users::dsl::*;
+
+    Ok(users.find(user_id).first(conn)?)
+  }
+
+  /// Finds a user by username.
+  ///
+  /// # Arguments
+  /// * `name` - Username to find.
+  /// * `conn` - Database connection to use.
+  pub fn find_by_username(name: &str, conn: &diesel::pg::PgConnection) -> Result<User, HttpError> {
+    use crate::db::users::dsl::*;
+
+    Ok(users.filter(username.eq(name)).first(conn)?)
+  }
+
+  /// Names of every group the user is a member of.
+  ///
+  /// # Arguments
+  /// * `conn` - Database connection to use.
+  pub fn group_names(&self, conn: &diesel::pg::PgConnection) -> Result<Vec<String>, HttpError> {
+    use crate::db::{groups, user_groups};
+
+    Ok(user_groups::table
+      .inner_join(groups::table)
+      .filter(user_groups::user_id.eq(self.id))
+      .select(groups::name)
+      .order(groups::name.asc())
+      .load(conn)?)
+  }
+
+  /// Builds the Kubernetes `UserInfo` for this user.
+  /// The token's scopes are added to `extra` next to the user's own attributes.
+  ///
+  /// # Arguments
+  /// * `groups` - Names of the groups the user is a member of.
+  /// * `claims` - Claims of the token being reviewed.
+  pub fn user_info(&self, groups: Vec<String>, claims: &Claims) -> UserInfo {
+    let mut extra: BTreeMap<String, Vec<String>> = serde_json::from_value(self.extra.clone()).unwrap_or_else(|e| {
+      warn!("Ignoring malformed extra attributes of user {}: {}", self.id, e);
+      BTreeMap::new()
+    });
+    extra.insert("scopes".to_owned(), claims.scopes.clone());
+
+    UserInfo {
+      username: Some(self.username.clone()),
+      uid: Some(self.id.to_string()),
+      groups: Some(groups),
+      extra: Some(extra)
+    }
+  }
+}
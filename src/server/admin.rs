@@ -0,0 +1,187 @@
+use actix_service::{Service, Transform};
+use actix_web::{Error, web, dev::{ServiceRequest, ServiceResponse}, http::header::AUTHORIZATION};
+use futures::future::{Either, Future, FutureResult, ok};
+use futures::Poll;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::token;
+use crate::settings;
+use crate::db::Database;
+use crate::models::Token;
+use crate::signing::KeyRing;
+use crate::server::errors::HttpError;
+
+/// Middleware restricting a resource to administrators: callers must present a bearer token issued by this service,
+/// still active, and carrying the admin scope.
+/// Requests without a usable token get `401`, tokens without the scope get `403`.
+#[derive(Clone)]
+pub struct AdminAuth {
+  keys: KeyRing,
+  database: Database,
+  scope: String
+}
+
+impl AdminAuth {
+  /// Creates the middleware using settings.
+  ///
+  /// # Arguments
+  /// * `admin`    - Admin settings to use.
+  /// * `keys`     - Key ring verifying admin tokens.
+  /// * `database` - Database holding issued tokens.
+  pub fn from_settings(admin: &settings::Admin, keys: &KeyRing, database: &Database) -> Self {
+    AdminAuth { keys: keys.clone(), database: database.clone(), scope: admin.scope.to_owned() }
+  }
+
+  /// Checks that a bearer token may administer this service.
+  /// Blocks on the database, so call it from a blocking thread.
+  ///
+  /// # Arguments
+  /// * `bearer` - Token presented by the caller.
+  pub fn verify(&self, bearer: &str) -> Result<Token, HttpError> {
+    let claims = token::decode(&self.keys, bearer)?;
+    let conn   = self.database.pool.get()?;
+    let stored = match Token::find_active(claims.jti, &conn) {
+      Ok(stored)               => stored,
+      Err(HttpError::NotFound) => return Err(HttpError::Unauthorized),
+      Err(e)                   => return Err(e)
+    };
+
+    if !stored.claims.scopes.contains(&self.scope) {
+      warn!("Token {} of user {} lacks the {} scope", stored.id, stored.user_id, self.scope);
+      return Err(HttpError::Forbidden);
+    }
+    Ok(stored)
+  }
+}
+
+impl<S, B> Transform<S> for AdminAuth
+  where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static {
+
+  type Request = ServiceRequest;
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type InitError = ();
+  type Transform = AdminAuthMiddleware<S>;
+  type Future = FutureResult<Self::Transform, Self::InitError>;
+
+  fn new_transform(&self, service: S) -> Self::Future {
+    ok(AdminAuthMiddleware { service: Rc::new(RefCell::new(service)), auth: self.clone() })
+  }
+}
+
+pub struct AdminAuthMiddleware<S> {
+  service: Rc<RefCell<S>>,
+  auth: AdminAuth
+}
+
+impl<S, B> Service for AdminAuthMiddleware<S>
+  where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static {
+
+  type Request = ServiceRequest;
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error>>;
+
+  fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+    self.service.borrow_mut().poll_ready()
+  }
+
+  fn call(&mut self, req: ServiceRequest) -> Self::Future {
+    // Refuse requests without a token right away, without touching the database
+    let bearer = match bearer_token(&req) {
+      Some(bearer) => bearer,
+      None         => return Box::new(ok(req.error_response(HttpError::Unauthorized)))
+    };
+
+    let auth    = self.auth.clone();
+    let service = self.service.clone();
+
+    Box::new(
+      web::block(move || auth.verify(&bearer)).then(move |res| match res {
+        Ok(stored) => {
+          debug!("Admin request {} {} by user {}", req.method(), req.path(), stored.user_id);
+          Either::A(service.borrow_mut().call(req))
+        },
+        Err(e) => Either::B(ok(req.error_response(HttpError::from(e))))
+      })
+    )
+  }
+}
+
+/// Token of an `Authorization: Bearer` header.
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+  let header = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+  match header.get(..7) {
+    Some(scheme) if scheme.eq_ignore_ascii_case("bearer ") => Some(header[7..].trim().to_owned()).filter(|token| !token.is_empty()),
+    _ => None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use actix_web::{test, App, HttpResponse, http::StatusCode};
+  use diesel::pg::PgConnection;
+  use diesel::r2d2::{ConnectionManager, Pool};
+  use jsonwebtoken::Algorithm;
+  use super::*;
+  use crate::signing::SigningKey;
+
+  /// Admin check over a database that is never reached, tokens are refused before any lookup.
+  fn auth() -> AdminAuth {
+    let key = SigningKey::from_config(&settings::KeyConfig {
+      algorithm: Algorithm::HS256,
+      kid: None,
+      secret: Some("kitty".into()),
+      private_key: None,
+      public_key: None,
+      retire_at: None
+    }).unwrap();
+
+    let keys     = KeyRing { issuer: "heimdallr".into(), active: key, previous: vec![] };
+    let database = Database { pool: Pool::builder().build_unchecked(ConnectionManager::<PgConnection>::new("postgres://localhost/unused")) };
+    AdminAuth::from_settings(&settings::Admin::default(), &keys, &database)
+  }
+
+  fn status(request: test::TestRequest) -> StatusCode {
+    let mut app = test::init_service(
+      App::new().service(
+        web::resource("/api/tokens")
+          .route(web::post().to(|| HttpResponse::Created()))
+          .wrap(auth())
+      )
+    );
+    test::call_service(&mut app, request.uri("/api/tokens").to_request()).status()
+  }
+
+  fn revoke_status(request: test::TestRequest) -> StatusCode {
+    let mut app = test::init_service(
+      App::new().service(
+        web::resource("/api/tokens/{id}")
+          .route(web::delete().to(|| HttpResponse::NoContent()))
+          .wrap(auth())
+      )
+    );
+    test::call_service(&mut app, request.uri("/api/tokens/0d3c6d2e-8f57-4d7c-9a0e-12f6b3c1a2b4").to_request()).status()
+  }
+
+  speculate! {
+    it "refuses requests without an admin token" {
+      assert_eq!(status(test::TestRequest::post()), StatusCode::UNAUTHORIZED);
+      assert_eq!(status(test::TestRequest::post().header(AUTHORIZATION, "Basic a2l0dHk6a2l0dHk=")), StatusCode::UNAUTHORIZED);
+      assert_eq!(status(test::TestRequest::post().header(AUTHORIZATION, "Bearer kitty")), StatusCode::UNAUTHORIZED);
+    }
+
+    it "refuses revocations without an admin token" {
+      assert_eq!(revoke_status(test::TestRequest::delete()), StatusCode::UNAUTHORIZED);
+      assert_eq!(revoke_status(test::TestRequest::delete().header(AUTHORIZATION, "Bearer kitty")), StatusCode::UNAUTHORIZED);
+    }
+  }
+}
@@ -0,0 +1,54 @@
+use actix_web::{Error, HttpResponse, web};
+use futures::future::Future;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::models::{AuthEvent, User};
+use crate::server::errors::HttpError;
+
+/// Events returned when no limit is given.
+const DEFAULT_LIMIT: i64 = 100;
+
+/// Most events returned by a single request.
+const MAX_LIMIT: i64 = 1000;
+
+/// Query parameters accepted when reading the audit log.
+#[derive(Debug, Deserialize)]
+pub struct AuditParams {
+  /// Id or username of the user whose events to return.
+  pub user: Option<String>,
+
+  /// Only return events recorded at or after this time (RFC 3339).
+  pub since: Option<DateTime<Utc>>,
+
+  /// Maximum number of events to return, newest first.
+  pub limit: Option<i64>
+}
+
+/// HTTP handler for reading recorded authentication decisions.
+pub fn handler(params: web::Query<AuditParams>, db: web::Data<Database>) -> impl Future<Item = HttpResponse, Error = Error> {
+  let params = params.into_inner();
+
+  web::block(move || -> Result<Vec<AuthEvent>, HttpError> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit < 1 || limit > MAX_LIMIT {
+      return Err(HttpError::BadRequest(format!("limit must be between 1 and {}", MAX_LIMIT)));
+    }
+
+    let conn  = db.pool.get()?;
+    let owner = match params.user {
+      Some(ref user) => match Uuid::parse_str(user) {
+        Ok(id) => Some(id),
+        Err(_) => Some(User::find_by_username(user, &conn)?.id)
+      },
+      None => None
+    };
+
+    AuthEvent::list(owner, params.since.map(|since| since.naive_utc()), limit, &conn)
+  })
+  .map_err(HttpError::from)
+  .from_err()
+  .map(|events| HttpResponse::Ok().json(events))
+}
@@ -1,40 +1,110 @@
-use jsonwebtoken::{decode as jwt_decode, Header, Algorithm, Validation};
-use actix_web::{Error, HttpResponse, web};
-use futures::future::{Future, IntoFuture, Either, ok, err, result};
-use serde::Deserialize;
-
-#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
-pub struct Claims {
-  pub sub: String,
-  pub aud: String,
-  pub iat: i64,
-  pub exp: i64,
-  pub nbf: i64
-}
+use actix_web::{Error, HttpRequest, HttpResponse, web};
+use futures::future::{Future, ok};
+use chrono::NaiveDateTime;
 
-use crate::db::Database;
+use crate::token;
+use crate::models::NewAuthEvent;
+use crate::authenticators::{Chain, Decision, Review, TokenDetails};
+use crate::server::audit::AuditLog;
+use crate::server::cache::{CachedDecision, DecisionCache};
 use crate::server::errors::HttpError;
-use crate::kubernetes::authentication::v1beta1::{TokenReview, TokenReviewStatus};
+use crate::server::metrics::Metrics;
+use crate::kubernetes::authentication::VersionedTokenReview;
+use crate::kubernetes::authentication::v1::TokenReviewStatus;
 
 /// HTTP handler token authentication.
-pub fn handler(token_review: web::Json<TokenReview>, _db: web::Data<Database>) -> impl Future<Item = HttpResponse, Error = Error> {
+/// Accepts any supported TokenReview version and replies in the version of the request.
+/// Successful decisions are served from the decision cache while fresh.
+pub fn handler(req: HttpRequest, token_review: web::Json<VersionedTokenReview>, audit: web::Data<AuditLog>, chain: web::Data<Chain>, metrics: web::Data<Metrics>, cache: web::Data<DecisionCache>) -> impl Future<Item = HttpResponse, Error = Error> {
   let token_review = token_review.into_inner();
-  
+  let fingerprint  = token::fingerprint(&token_review.spec().token);
+  let audiences    = token_review.spec().audiences.to_owned().unwrap_or_default();
+  let remote_addr  = req.peer_addr().map(|addr| addr.ip().to_string());
+
   debug!("Parsing TokenReview request = {:?}", token_review);
 
-  let mut response = token_review.to_owned();
-  web::block(move || {
-    jwt_decode::<Claims>(&token_review.spec.token, "supercalifragilisticexpialidocious".as_ref(), &Validation::default())
-  })
-  .then(move |res| match res {
-    Ok(token) => {
-      response.status = TokenReviewStatus::denied();
-      ok(HttpResponse::Unauthorized().json(response))
-    },
-    Err(e) => {
-      debug!("ERROR = {:?}", e);
-      response.status = TokenReviewStatus::denied();
-      ok(HttpResponse::Unauthorized().json(response))
+  let mut response       = token_review.to_owned();
+  let review_fingerprint = fingerprint.clone();
+  let review_audiences   = audiences.clone();
+  let review_metrics     = metrics.clone();
+
+  let reviewed = web::block(move || -> Result<Review, HttpError> {
+    let spec = token_review.spec();
+
+    if cache.is_enabled() {
+      let cached = cache.get(&spec.token, &review_audiences);
+      review_metrics.cache_lookup(cached.is_some());
+
+      if let Some(cached) = cached {
+        debug!("Token {} found in the decision cache", review_fingerprint);
+        return Ok(from_cache(cached));
+      }
+    }
+
+    let generation = cache.generation();
+    let review     = chain.authenticate(spec, &review_fingerprint)?;
+    if let Some((expires_at, cached)) = to_cache(&review) {
+      cache.insert(&spec.token, &review_audiences, expires_at, generation, cached);
     }
-  })
+    Ok(review)
+  });
+
+  reviewed
+    .then(move |res| {
+      let mut event = NewAuthEvent { jti: None, user_id: None, decision: String::new(), reason: None, audiences, remote_addr };
+
+      let response = match res {
+        Ok(Review { decision: Decision::Authenticated(user, audiences, details), .. }) => {
+          debug!("Authenticated token {} as {}", fingerprint, user.username.as_ref().map_or("", String::as_str));
+          metrics.token_review("authenticated", "ok");
+          event.jti      = details.jti;
+          event.user_id  = details.user_id;
+          event.decision = "authenticated".to_owned();
+          response.set_status(TokenReviewStatus::authenticated(user, audiences));
+          HttpResponse::Ok().json(response)
+        },
+        Ok(Review { decision: Decision::Denied(reason, details), .. }) => {
+          debug!("Denied token {}: {}", fingerprint, reason);
+          metrics.token_review("denied", reason);
+          event.jti      = details.jti;
+          event.user_id  = details.user_id;
+          event.decision = "denied".to_owned();
+          event.reason   = Some(reason.to_owned());
+          response.set_status(TokenReviewStatus::denied());
+          HttpResponse::Ok().json(response)
+        },
+        Err(e) => {
+          error!("Unable to review token {}: {:?}", fingerprint, e);
+          metrics.token_review("error", "internal");
+          event.decision = "error".to_owned();
+          event.reason   = Some("internal".to_owned());
+          response.set_status(TokenReviewStatus::errored("Unable to review token"));
+          HttpResponse::InternalServerError().json(response)
+        }
+      };
+
+      audit.record(event);
+      ok(response)
+    })
+}
+
+/// Rebuilds a review from a cached decision.
+fn from_cache(cached: CachedDecision) -> Review {
+  let details = TokenDetails { jti: Some(cached.jti), user_id: Some(cached.user_id), expires_at: None };
+  Review { authenticator: None, decision: Decision::Authenticated(cached.user, cached.audiences, details), degraded: false }
+}
+
+/// The review in cacheable form, if it fully authenticated a token that revocations can invalidate.
+/// Users missing their directory groups are not cached, so they get them back as soon as the directory recovers.
+fn to_cache(review: &Review) -> Option<(NaiveDateTime, CachedDecision)> {
+  if review.degraded {
+    return None;
+  }
+
+  match review.decision {
+    Decision::Authenticated(ref user, ref audiences, TokenDetails { jti: Some(jti), user_id: Some(user_id), expires_at: Some(expires_at) }) => {
+      Some((expires_at, CachedDecision { jti, user_id, user: user.to_owned(), audiences: audiences.to_owned() }))
+    },
+    _ => None
+  }
 }
@@ -0,0 +1,40 @@
+use actix_web::{Error, HttpResponse, web};
+use futures::future::{Future, ok};
+
+use crate::db::Database;
+use crate::models::AuthorizationRule;
+use crate::server::errors::HttpError;
+use crate::kubernetes::authorization::v1::{SubjectAccessReview, SubjectAccessReviewStatus};
+
+/// HTTP handler for SubjectAccessReview authorization.
+pub fn handler(access_review: web::Json<SubjectAccessReview>, db: web::Data<Database>) -> impl Future<Item = HttpResponse, Error = Error> {
+  let access_review = access_review.into_inner();
+
+  debug!("Parsing SubjectAccessReview request = {:?}", access_review);
+
+  let mut response = access_review.to_owned();
+  web::block(move || -> Result<Option<SubjectAccessReviewStatus>, HttpError> {
+    let spec   = &access_review.spec;
+    let user   = spec.user.as_ref().map_or("", String::as_str);
+    let groups = spec.groups.to_owned().unwrap_or_default();
+
+    let conn  = db.pool.get()?;
+    let rules = AuthorizationRule::for_subject(user, &groups, &conn)?;
+
+    Ok(match rules.iter().find(|rule| rule.allows(spec)) {
+      Some(rule) => SubjectAccessReviewStatus::allowed(rule.describe()),
+      None       => SubjectAccessReviewStatus::no_opinion(format!("no rule allows {:?} with groups {:?}", user, groups))
+    })
+  })
+  .then(move |res| match res {
+    Ok(status) => {
+      response.status = status;
+      ok(HttpResponse::Ok().json(response))
+    },
+    Err(e) => {
+      error!("Unable to review access: {:?}", e);
+      response.status = SubjectAccessReviewStatus::errored("Unable to review access");
+      ok(HttpResponse::InternalServerError().json(response))
+    }
+  })
+}
@@ -1,9 +1,13 @@
 use actix_web::{Error, HttpResponse, web};
-use futures::future::{Future, result};
+use futures::future::{Future, ok, result};
 use serde::Serialize;
+use std::time::Duration;
 
 use crate::db::Database;
 
+/// Longest time a readiness probe waits on the database.
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
 #[derive(Serialize)]
 pub struct HealthResponse<'a> {
   pub healthy: bool,
@@ -25,7 +29,56 @@ impl<'a> HealthResponse<'a> {
   }
 }
 
-/// HTTP handler for health checks.
+#[derive(Serialize)]
+pub struct ReadinessResponse<'a> {
+  pub ready: bool,
+  pub version: &'a str,
+  pub database: DatabaseStatus
+}
+
+#[derive(Serialize)]
+pub struct DatabaseStatus {
+  pub reachable: bool,
+  pub connections: u32,
+  pub idle_connections: u32,
+  pub in_use_connections: u32,
+  pub error: Option<String>
+}
+
+/// HTTP handler for liveness checks.
+/// The process answering is all this reports, so a database outage does not get the pod restarted.
 pub fn handler(_db: web::Data<Database>) -> impl Future<Item = HttpResponse, Error = Error> {
   result(Ok(HttpResponse::Ok().json(HealthResponse::create())))
 }
+
+/// HTTP handler for readiness checks.
+/// Responds with 503 while Postgres is unreachable so Kubernetes stops routing traffic here.
+pub fn readiness(db: web::Data<Database>) -> impl Future<Item = HttpResponse, Error = Error> {
+  let pool = db.pool.clone();
+
+  web::block(move || db.ping(READINESS_TIMEOUT))
+    .then(move |res| {
+      let state = pool.state();
+      let error = res.err().map(|e| e.to_string());
+
+      let response = ReadinessResponse {
+        ready: error.is_none(),
+        version: env!("CARGO_PKG_VERSION"),
+        database: DatabaseStatus {
+          reachable: error.is_none(),
+          connections: state.connections,
+          idle_connections: state.idle_connections,
+          in_use_connections: state.connections - state.idle_connections,
+          error
+        }
+      };
+
+      if response.ready {
+        ok(HttpResponse::Ok().json(response))
+      }
+      else {
+        warn!("Readiness check failed: {}", response.database.error.as_ref().map_or("", String::as_str));
+        ok(HttpResponse::ServiceUnavailable().json(response))
+      }
+    })
+}
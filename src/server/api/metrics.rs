@@ -0,0 +1,15 @@
+use actix_web::{HttpResponse, web};
+
+use crate::db::Database;
+use crate::server::metrics::Metrics;
+
+/// HTTP handler exposing metrics in the Prometheus text format.
+pub fn handler(metrics: web::Data<Metrics>, db: web::Data<Database>) -> HttpResponse {
+  match metrics.render(&db) {
+    Ok(body) => HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(body),
+    Err(e)   => {
+      error!("Unable to render metrics: {:?}", e);
+      HttpResponse::InternalServerError().finish()
+    }
+  }
+}
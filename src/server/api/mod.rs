@@ -1,5 +1,24 @@
+mod audit;
+pub use audit::handler as audit;
+
 mod authenticate;
 pub use authenticate::handler as authenticate;
 
+mod authorize;
+pub use authorize::handler as authorize;
+
 mod healthz;
 pub use healthz::handler as healthz;
+pub use healthz::readiness as readyz;
+
+mod metrics;
+pub use metrics::handler as metrics;
+
+mod tokens;
+pub use tokens::create as create_token;
+pub use tokens::revoke as revoke_token;
+pub use tokens::revoke_for_user as revoke_user_tokens;
+
+mod well_known;
+pub use well_known::jwks;
+pub use well_known::openid_configuration;
@@ -0,0 +1,141 @@
+use actix_web::{Error, HttpResponse, web};
+use futures::future::Future;
+use chrono::{NaiveDateTime, Utc, Duration};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use uuid::Uuid;
+use std::fmt;
+
+use crate::token::{self, validate_ttl};
+use crate::db::Database;
+use crate::models::Token;
+use crate::signing::KeyRing;
+use crate::server::cache::DecisionCache;
+use crate::server::errors::HttpError;
+use crate::server::metrics::Metrics;
+
+/// Request body for issuing a token.
+#[derive(Debug, Deserialize, Validate)]
+pub struct TokenRequest {
+  /// User the token is issued to.
+  pub user_id: Uuid,
+
+  /// Scopes granted to the token.
+  #[serde(default)]
+  pub scopes: Vec<String>,
+
+  /// Audiences the token is intended for (`aud` claim).
+  #[serde(default)]
+  pub audiences: Vec<String>,
+
+  /// Lifetime of the token in seconds (1 minute to 1 year).
+  #[validate(custom = "validate_ttl")]
+  pub ttl: i64
+}
+
+/// Response body for a newly issued token.
+/// This is the only time the encoded token is ever returned.
+#[derive(Serialize)]
+pub struct TokenResponse {
+  pub id: Uuid,
+  pub user_id: Uuid,
+  pub scopes: Vec<String>,
+  pub audiences: Vec<String>,
+  pub expires_at: NaiveDateTime,
+  pub token: String
+}
+
+/// Keeps the encoded token out of logs.
+impl fmt::Debug for TokenResponse {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("TokenResponse")
+      .field("id", &self.id)
+      .field("user_id", &self.user_id)
+      .field("scopes", &self.scopes)
+      .field("audiences", &self.audiences)
+      .field("expires_at", &self.expires_at)
+      .field("token", &format_args!("<redacted {}>", token::fingerprint(&self.token)))
+      .finish()
+  }
+}
+
+/// Query parameters accepted when revoking tokens.
+#[derive(Debug, Deserialize)]
+pub struct RevokeParams {
+  pub reason: Option<String>
+}
+
+/// Response body for a bulk revocation.
+#[derive(Debug, Serialize)]
+pub struct RevokedResponse {
+  pub revoked: Vec<Uuid>
+}
+
+/// HTTP handler for issuing tokens.
+pub fn create(request: web::Json<TokenRequest>, db: web::Data<Database>, keys: web::Data<KeyRing>, metrics: web::Data<Metrics>) -> impl Future<Item = HttpResponse, Error = Error> {
+  let request = request.into_inner();
+
+  web::block(move || -> Result<TokenResponse, HttpError> {
+    request.validate()?;
+
+    if !keys.active.can_sign() {
+      return Err(HttpError::NotImplemented);
+    }
+
+    let expires_at = (Utc::now() + Duration::seconds(request.ttl)).naive_utc();
+    let conn       = db.pool.get()?;
+    let token      = Token::new(request.user_id, request.scopes, request.audiences, &keys.issuer, expires_at, &conn)?;
+    let encoded    = token::encode(&keys, &token.claims)?;
+
+    info!("Issued token {} to user {}", token.id, token.user_id);
+    metrics.tokens_issued.inc();
+
+    Ok(TokenResponse {
+      id: token.id,
+      user_id: token.user_id,
+      scopes: token.claims.scopes,
+      audiences: token.claims.aud,
+      expires_at: token.expires_at,
+      token: encoded
+    })
+  })
+  .map_err(HttpError::from)
+  .from_err()
+  .map(|response| HttpResponse::Created().json(response))
+}
+
+/// HTTP handler for revoking a single token.
+pub fn revoke(token_id: web::Path<Uuid>, params: web::Query<RevokeParams>, db: web::Data<Database>, metrics: web::Data<Metrics>, cache: web::Data<DecisionCache>) -> impl Future<Item = HttpResponse, Error = Error> {
+  let token_id = token_id.into_inner();
+  let reason   = params.into_inner().reason;
+
+  web::block(move || -> Result<Token, HttpError> {
+    let conn  = db.pool.get()?;
+    let token = Token::revoke(token_id, reason, Some(&cache), &conn)?;
+
+    info!("Revoked token {} of user {}", token.id, token.user_id);
+    metrics.tokens_revoked.inc();
+    Ok(token)
+  })
+  .map_err(HttpError::from)
+  .from_err()
+  .map(|token| HttpResponse::Ok().json(token))
+}
+
+/// HTTP handler for revoking every token of a user (eg when offboarding).
+pub fn revoke_for_user(user_id: web::Path<Uuid>, params: web::Query<RevokeParams>, db: web::Data<Database>, metrics: web::Data<Metrics>, cache: web::Data<DecisionCache>) -> impl Future<Item = HttpResponse, Error = Error> {
+  let user_id = user_id.into_inner();
+  let reason  = params.into_inner().reason;
+
+  web::block(move || -> Result<RevokedResponse, HttpError> {
+    let conn   = db.pool.get()?;
+    let tokens = Token::revoke_all_for_user(user_id, reason, Some(&cache), &conn)?;
+
+    info!("Revoked {} token(s) of user {}", tokens.len(), user_id);
+    metrics.tokens_revoked.inc_by(tokens.len() as i64);
+    Ok(RevokedResponse { revoked: tokens.into_iter().map(|token| token.id).collect() })
+  })
+  .map_err(HttpError::from)
+  .from_err()
+  .map(|response| HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,41 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Serialize;
+
+use crate::signing::KeyRing;
+
+/// OpenID Connect discovery document, limited to what offline token verification needs.
+#[derive(Debug, Serialize)]
+pub struct OpenIdConfiguration {
+  pub issuer: String,
+  pub jwks_uri: String,
+  pub response_types_supported: Vec<&'static str>,
+  pub subject_types_supported: Vec<&'static str>,
+  pub id_token_signing_alg_values_supported: Vec<String>,
+  pub claims_supported: Vec<&'static str>
+}
+
+/// HTTP handler for the JSON Web Key Set.
+pub fn jwks(keys: web::Data<KeyRing>) -> HttpResponse {
+  HttpResponse::Ok().json(keys.jwks())
+}
+
+/// HTTP handler for the OpenID discovery document.
+pub fn openid_configuration(req: HttpRequest, keys: web::Data<KeyRing>) -> HttpResponse {
+  // Prefer the configured issuer when it is a URL, otherwise point at whoever answered this request
+  let base_url = if keys.issuer.starts_with("https://") || keys.issuer.starts_with("http://") {
+    keys.issuer.trim_end_matches('/').to_owned()
+  }
+  else {
+    let info = req.connection_info();
+    format!("{}://{}", info.scheme(), info.host())
+  };
+
+  HttpResponse::Ok().json(OpenIdConfiguration {
+    issuer: keys.issuer.to_owned(),
+    jwks_uri: format!("{}/.well-known/jwks.json", base_url),
+    response_types_supported: vec!["id_token"],
+    subject_types_supported: vec!["public"],
+    id_token_signing_alg_values_supported: keys.algorithms().iter().map(|alg| format!("{:?}", alg)).collect(),
+    claims_supported: vec!["sub", "iss", "iat", "exp", "nbf", "jti", "user_id", "scopes"]
+  })
+}
@@ -0,0 +1,133 @@
+use failure::Fallible;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::thread;
+
+use crate::settings;
+use crate::db::Database;
+use crate::models::NewAuthEvent;
+use crate::server::errors::HttpError;
+use crate::server::metrics::Metrics;
+
+/// Queue of audit events, stored in batches by a single background writer so reviews never wait on the database.
+/// When the writer falls behind and the queue is full, events are dropped and counted rather than queued unbounded.
+#[derive(Clone)]
+pub struct AuditLog {
+  events: SyncSender<NewAuthEvent>,
+  metrics: Metrics
+}
+
+impl AuditLog {
+  /// Creates the queue using settings and starts its writer.
+  ///
+  /// # Arguments
+  /// * `audit`    - Audit settings to use.
+  /// * `database` - Database to store events in.
+  /// * `metrics`  - Metrics to count dropped events in.
+  pub fn from_settings(audit: &settings::Audit, database: &Database, metrics: &Metrics) -> Fallible<Self> {
+    let (log, events) = Self::new(audit.queue_size, metrics.clone());
+    let writer        = Writer { events, database: database.clone(), batch_size: audit.batch_size.max(1), metrics: metrics.clone() };
+
+    thread::Builder::new()
+      .name("audit-writer".to_owned())
+      .spawn(move || writer.run())?;
+
+    Ok(log)
+  }
+
+  /// Creates the queue, returning the end events are read from.
+  ///
+  /// # Arguments
+  /// * `queue_size` - Most events waiting to be stored.
+  /// * `metrics`    - Metrics to count dropped events in.
+  fn new(queue_size: usize, metrics: Metrics) -> (Self, Receiver<NewAuthEvent>) {
+    let (events, receiver) = sync_channel(queue_size);
+    (AuditLog { events, metrics }, receiver)
+  }
+
+  /// Queues an event for storage without waiting.
+  ///
+  /// # Arguments
+  /// * `event` - Event to store.
+  pub fn record(&self, event: NewAuthEvent) {
+    match self.events.try_send(event) {
+      Ok(())                             => (),
+      Err(TrySendError::Full(_))         => self.metrics.audit_event_dropped("queue_full", 1),
+      Err(TrySendError::Disconnected(_)) => {
+        error!("Audit writer is gone, dropping event");
+        self.metrics.audit_event_dropped("write_error", 1);
+      }
+    }
+  }
+}
+
+/// Background loop storing queued events.
+struct Writer {
+  events: Receiver<NewAuthEvent>,
+  database: Database,
+  batch_size: usize,
+  metrics: Metrics
+}
+
+impl Writer {
+  fn run(self) {
+    while let Some(batch) = next_batch(&self.events, self.batch_size) {
+      let stored = self.database.pool.get()
+        .map_err(HttpError::from)
+        .and_then(|conn| NewAuthEvent::insert_all(&batch, &conn));
+
+      if let Err(e) = stored {
+        error!("Unable to record {} auth events: {:?}", batch.len(), e);
+        self.metrics.audit_event_dropped("write_error", batch.len());
+      }
+    }
+  }
+}
+
+/// Waits for the next event and takes whatever else is already queued, up to `batch_size` events.
+/// Returns `None` once every sender is gone and the queue is drained.
+fn next_batch(events: &Receiver<NewAuthEvent>, batch_size: usize) -> Option<Vec<NewAuthEvent>> {
+  let mut batch = vec![events.recv().ok()?];
+  while batch.len() < batch_size {
+    match events.try_recv() {
+      Ok(event) => batch.push(event),
+      Err(_)    => break
+    }
+  }
+  Some(batch)
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  fn event(decision: &str) -> NewAuthEvent {
+    NewAuthEvent { jti: None, user_id: None, decision: decision.into(), reason: None, audiences: vec![], remote_addr: None }
+  }
+
+  speculate! {
+    it "drops and counts events once the queue is full" {
+      let metrics       = Metrics::new().unwrap();
+      let (log, events) = AuditLog::new(2, metrics.clone());
+      for decision in &["authenticated", "denied", "error"] {
+        log.record(event(decision));
+      }
+
+      assert_eq!(metrics.audit_events_dropped.with_label_values(&["queue_full"]).get(), 1);
+      assert_eq!(events.try_iter().map(|event| event.decision).collect::<Vec<_>>(), vec!["authenticated", "denied"]);
+    }
+
+    it "batches queued events" {
+      let (log, events) = AuditLog::new(10, Metrics::new().unwrap());
+      for _ in 0..5 {
+        log.record(event("denied"));
+      }
+
+      assert_eq!(next_batch(&events, 3).map(|batch| batch.len()), Some(3));
+      assert_eq!(next_batch(&events, 3).map(|batch| batch.len()), Some(2));
+
+      drop(log);
+      assert!(next_batch(&events, 3).is_none());
+    }
+  }
+}
@@ -0,0 +1,245 @@
+use chrono::{NaiveDateTime, Utc};
+use openssl::sha::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::settings;
+use crate::kubernetes::authentication::v1::UserInfo;
+
+/// A successful TokenReview, as remembered by the cache.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedDecision {
+  pub jti: Uuid,
+  pub user_id: Uuid,
+  pub user: UserInfo,
+  pub audiences: Vec<String>
+}
+
+struct Entry {
+  decision: CachedDecision,
+  expires_at: Instant
+}
+
+#[derive(Default)]
+struct Entries {
+  map: HashMap<[u8; 32], Entry>,
+
+  /// Bumped by every invalidation, so a review that raced a revocation is not cached.
+  generation: u64
+}
+
+/// Bounded TTL cache of successful TokenReview decisions, keyed by a hash of the token and the requested audiences.
+/// Only authenticated decisions are cached, so revoking a token or user has to invalidate its entries.
+/// Hits never touch the database: revocations this process does not see (the CLI, other replicas) are only picked up
+/// once their entries expire, which `ttl` bounds.
+#[derive(Clone)]
+pub struct DecisionCache {
+  entries: Arc<Mutex<Entries>>,
+  capacity: usize,
+  ttl: Duration
+}
+
+impl DecisionCache {
+  /// Creates a cache using settings.
+  ///
+  /// # Arguments
+  /// * `settings` - Cache settings to use.
+  pub fn from_settings(settings: &settings::Cache) -> Self {
+    let capacity = if settings.enabled { settings.capacity } else { 0 };
+    Self::new(capacity, Duration::from_secs(settings.ttl))
+  }
+
+  /// Creates a cache.
+  ///
+  /// # Arguments
+  /// * `capacity` - Most decisions kept at once, `0` disables the cache.
+  /// * `ttl`      - How long a decision is kept.
+  pub fn new(capacity: usize, ttl: Duration) -> Self {
+    DecisionCache { entries: Arc::new(Mutex::new(Entries::default())), capacity, ttl }
+  }
+
+  /// Whether decisions are cached at all.
+  pub fn is_enabled(&self) -> bool {
+    self.capacity > 0
+  }
+
+  /// Current invalidation generation, to be taken before reviewing a token and passed to `insert`.
+  pub fn generation(&self) -> u64 {
+    self.lock().generation
+  }
+
+  /// Looks up the decision for a token and audiences, if it is still fresh.
+  ///
+  /// # Arguments
+  /// * `token`     - Bearer token under review.
+  /// * `audiences` - Audiences requested by the review.
+  pub fn get(&self, token: &str, audiences: &[String]) -> Option<CachedDecision> {
+    if !self.is_enabled() {
+      return None;
+    }
+
+    let key         = key(token, audiences);
+    let mut entries = self.lock();
+    match entries.map.get(&key) {
+      Some(entry) if entry.expires_at > Instant::now() => Some(entry.decision.clone()),
+      Some(_) => {
+        entries.map.remove(&key);
+        None
+      },
+      None => None
+    }
+  }
+
+  /// Remembers a decision, for no longer than the token itself is valid.
+  /// Nothing is remembered if anything was invalidated since `generation` was taken.
+  ///
+  /// # Arguments
+  /// * `token`            - Bearer token under review.
+  /// * `audiences`        - Audiences requested by the review.
+  /// * `token_expires_at` - When the token expires.
+  /// * `generation`       - Generation taken before the review started.
+  /// * `decision`         - Decision to remember.
+  pub fn insert(&self, token: &str, audiences: &[String], token_expires_at: NaiveDateTime, generation: u64, decision: CachedDecision) {
+    if !self.is_enabled() {
+      return;
+    }
+
+    let remaining = match (token_expires_at - Utc::now().naive_utc()).to_std() {
+      Ok(remaining) => remaining,
+      Err(_)        => return
+    };
+    let expires_at = Instant::now() + self.ttl.min(remaining);
+
+    let mut entries = self.lock();
+    if entries.generation != generation {
+      return;
+    }
+
+    if entries.map.len() >= self.capacity {
+      let now = Instant::now();
+      entries.map.retain(|_, entry| entry.expires_at > now);
+    }
+
+    // Still full of fresh entries, make room by dropping the one closest to expiring
+    if entries.map.len() >= self.capacity {
+      let oldest = entries.map.iter().min_by_key(|(_, entry)| entry.expires_at).map(|(key, _)| *key);
+      if let Some(oldest) = oldest {
+        entries.map.remove(&oldest);
+      }
+    }
+
+    entries.map.insert(key(token, audiences), Entry { decision, expires_at });
+  }
+
+  /// Forgets every decision about a token.
+  ///
+  /// # Arguments
+  /// * `jti` - Token to forget.
+  pub fn invalidate_token(&self, jti: Uuid) {
+    let mut entries = self.lock();
+    entries.generation += 1;
+    entries.map.retain(|_, entry| entry.decision.jti != jti);
+  }
+
+  /// Forgets every decision about a user's tokens.
+  ///
+  /// # Arguments
+  /// * `user_id` - User whose tokens to forget.
+  pub fn invalidate_user(&self, user_id: Uuid) {
+    let mut entries = self.lock();
+    entries.generation += 1;
+    entries.map.retain(|_, entry| entry.decision.user_id != user_id);
+  }
+
+  /// Number of cached decisions, including expired ones not yet evicted.
+  #[cfg(test)]
+  pub fn len(&self) -> usize {
+    self.lock().map.len()
+  }
+
+  /// Locks the entries, carrying on after a panic in another thread since they are always left consistent.
+  fn lock(&self) -> MutexGuard<Entries> {
+    self.entries.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+/// Hashes a token with the requested audiences, regardless of their order.
+fn key(token: &str, audiences: &[String]) -> [u8; 32] {
+  let mut audiences = audiences.to_vec();
+  audiences.sort();
+
+  let mut hasher = Sha256::new();
+  hasher.update(token.as_bytes());
+  for audience in &audiences {
+    hasher.update(b"\0");
+    hasher.update(audience.as_bytes());
+  }
+  hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use chrono::Duration as ChronoDuration;
+  use super::*;
+
+  fn decision(jti: Uuid, user_id: Uuid) -> CachedDecision {
+    CachedDecision { jti, user_id, user: UserInfo { username: Some("kitty".into()), ..Default::default() }, audiences: vec![] }
+  }
+
+  fn later() -> NaiveDateTime {
+    (Utc::now() + ChronoDuration::hours(1)).naive_utc()
+  }
+
+  speculate! {
+    it "returns cached decisions for the same token and audiences" {
+      let cache    = DecisionCache::new(10, Duration::from_secs(60));
+      let audience = vec!["a".to_owned(), "b".to_owned()];
+      let expected = decision(Uuid::new_v4(), Uuid::new_v4());
+      cache.insert("token", &audience, later(), cache.generation(), expected.clone());
+
+      assert_eq!(cache.get("token", &["b".to_owned(), "a".to_owned()]), Some(expected));
+      assert_eq!(cache.get("token", &[]), None);
+      assert_eq!(cache.get("other", &audience), None);
+    }
+
+    it "invalidates decisions by token and by user" {
+      let cache = DecisionCache::new(10, Duration::from_secs(60));
+      let (jti, user_id) = (Uuid::new_v4(), Uuid::new_v4());
+      cache.insert("one", &[], later(), cache.generation(), decision(jti, user_id));
+      cache.insert("two", &[], later(), cache.generation(), decision(Uuid::new_v4(), user_id));
+
+      cache.invalidate_token(jti);
+      assert_eq!(cache.get("one", &[]), None);
+      assert!(cache.get("two", &[]).is_some());
+
+      cache.invalidate_user(user_id);
+      assert_eq!(cache.get("two", &[]), None);
+    }
+
+    it "does not cache reviews that raced an invalidation" {
+      let cache      = DecisionCache::new(10, Duration::from_secs(60));
+      let generation = cache.generation();
+      cache.invalidate_user(Uuid::new_v4());
+      cache.insert("token", &[], later(), generation, decision(Uuid::new_v4(), Uuid::new_v4()));
+      assert_eq!(cache.get("token", &[]), None);
+    }
+
+    it "stays within its capacity" {
+      let cache = DecisionCache::new(2, Duration::from_secs(60));
+      for token in &["one", "two", "three"] {
+        cache.insert(token, &[], later(), cache.generation(), decision(Uuid::new_v4(), Uuid::new_v4()));
+      }
+      assert_eq!(cache.len(), 2);
+      assert!(cache.get("three", &[]).is_some());
+    }
+
+    it "does nothing when disabled" {
+      let cache = DecisionCache::new(0, Duration::from_secs(60));
+      cache.insert("token", &[], later(), cache.generation(), decision(Uuid::new_v4(), Uuid::new_v4()));
+      assert_eq!(cache.get("token", &[]), None);
+    }
+  }
+}
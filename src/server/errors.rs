@@ -1,5 +1,8 @@
-use actix_web::{http::StatusCode, error::ResponseError, HttpResponse};
+use actix_web::{http::StatusCode, error::{BlockingError, ResponseError}, HttpResponse};
 use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::r2d2::PoolError;
+use jsonwebtoken::errors::Error as JwtError;
+use validator::ValidationErrors;
 use serde::Serialize;
 use std::fmt;
 
@@ -61,6 +64,8 @@ impl fmt::Display for HttpError {
   }
 }
 
+impl std::error::Error for HttpError {}
+
 impl ResponseError for HttpError {
   fn error_response(&self) -> HttpResponse {
     match *self {
@@ -88,13 +93,46 @@ impl From<DieselError> for HttpError {
   fn from(error: DieselError) -> HttpError {
     match error {
       DieselError::DatabaseError(kind, info) => {
-        if let DatabaseErrorKind::UniqueViolation = kind {
-          let message = info.details().unwrap_or_else(|| info.message()).to_string();
-          return HttpError::BadRequest(message);
+        match kind {
+          DatabaseErrorKind::UniqueViolation | DatabaseErrorKind::ForeignKeyViolation => {
+            let message = info.details().unwrap_or_else(|| info.message()).to_string();
+            return HttpError::BadRequest(message);
+          },
+          _ => ()
         }
         HttpError::InternalServerError
       }
+      DieselError::NotFound => HttpError::NotFound,
       _ => HttpError::InternalServerError
     }
   }
 }
+
+impl From<PoolError> for HttpError {
+  fn from(error: PoolError) -> HttpError {
+    error!("Unable to check out a database connection: {}", error);
+    HttpError::InternalServerError
+  }
+}
+
+impl From<JwtError> for HttpError {
+  fn from(error: JwtError) -> HttpError {
+    debug!("Rejecting JWT: {:?}", error);
+    HttpError::Unauthorized
+  }
+}
+
+impl From<ValidationErrors> for HttpError {
+  fn from(errors: ValidationErrors) -> HttpError {
+    HttpError::BadRequest(errors.to_string())
+  }
+}
+
+impl From<BlockingError<HttpError>> for HttpError {
+  fn from(error: BlockingError<HttpError>) -> HttpError {
+    match error {
+      BlockingError::Error(error) => error,
+      BlockingError::Canceled     => HttpError::InternalServerError
+    }
+  }
+}
@@ -0,0 +1,375 @@
+use actix_service::{Service, Transform};
+use actix_web::{Error, HttpMessage, HttpRequest, dev::{MessageBody, ServiceRequest, ServiceResponse}};
+use futures::future::{Future, FutureResult, ok};
+use futures::Poll;
+use failure::Fallible;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use std::time::{Duration, Instant};
+
+use crate::db::Database;
+
+/// Route label used for requests that did not match any resource, to keep label cardinality bounded.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Pattern of the resource that matched a request, left in its extensions by `RouteLabel`.
+struct MatchedRoute(&'static str);
+
+/// Prometheus metrics exported on `/metrics`.
+/// Every metric is reference counted, so clones share the same values.
+#[derive(Clone)]
+pub struct Metrics {
+  registry: Registry,
+
+  /// TokenReview outcomes, labelled by `outcome` (authenticated, denied or error) and `reason`.
+  pub token_reviews: IntCounterVec,
+
+  /// Request latency, labelled by `method`, `route` and `status`.
+  pub request_duration: HistogramVec,
+
+  /// Answers of each authenticator in the chain, labelled by `authenticator` and `result`.
+  pub authenticator_results: IntCounterVec,
+
+  /// Time spent in each authenticator, labelled by `authenticator`.
+  pub authenticator_duration: HistogramVec,
+
+  /// Decision cache lookups, labelled by `result` (hit or miss).
+  pub cache_lookups: IntCounterVec,
+
+  /// LDAP group lookups, labelled by `result` (cached, found, not_found, error or skipped).
+  pub group_lookups: IntCounterVec,
+
+  /// Tokens issued through the API.
+  pub tokens_issued: IntCounter,
+
+  /// Tokens revoked through the API.
+  pub tokens_revoked: IntCounter,
+
+  /// Audit events that were never stored, labelled by `reason` (queue_full or write_error).
+  pub audit_events_dropped: IntCounterVec,
+
+  pool_connections: IntGauge,
+  pool_idle_connections: IntGauge
+}
+
+impl Metrics {
+  /// Creates and registers every metric.
+  pub fn new() -> Fallible<Self> {
+    let registry = Registry::new();
+
+    let token_reviews = IntCounterVec::new(
+      Opts::new("heimdallr_token_reviews_total", "TokenReview requests by outcome and reason"),
+      &["outcome", "reason"]
+    )?;
+    let request_duration = HistogramVec::new(
+      HistogramOpts::new("heimdallr_http_request_duration_seconds", "HTTP request latency by route"),
+      &["method", "route", "status"]
+    )?;
+    let authenticator_results = IntCounterVec::new(
+      Opts::new("heimdallr_authenticator_results_total", "Answers of each authenticator by result"),
+      &["authenticator", "result"]
+    )?;
+    let authenticator_duration = HistogramVec::new(
+      HistogramOpts::new("heimdallr_authenticator_duration_seconds", "Time spent in each authenticator"),
+      &["authenticator"]
+    )?;
+    let cache_lookups = IntCounterVec::new(
+      Opts::new("heimdallr_decision_cache_lookups_total", "TokenReview decision cache lookups by result"),
+      &["result"]
+    )?;
+    let group_lookups = IntCounterVec::new(
+      Opts::new("heimdallr_ldap_group_lookups_total", "LDAP group lookups by result"),
+      &["result"]
+    )?;
+    let audit_events_dropped = IntCounterVec::new(
+      Opts::new("heimdallr_audit_events_dropped_total", "Audit events dropped before being stored by reason"),
+      &["reason"]
+    )?;
+    let tokens_issued         = IntCounter::new("heimdallr_tokens_issued_total", "Tokens issued")?;
+    let tokens_revoked        = IntCounter::new("heimdallr_tokens_revoked_total", "Tokens revoked")?;
+    let pool_connections      = IntGauge::new("heimdallr_db_pool_connections", "Open database connections")?;
+    let pool_idle_connections = IntGauge::new("heimdallr_db_pool_idle_connections", "Idle database connections")?;
+
+    registry.register(Box::new(token_reviews.clone()))?;
+    registry.register(Box::new(request_duration.clone()))?;
+    registry.register(Box::new(authenticator_results.clone()))?;
+    registry.register(Box::new(authenticator_duration.clone()))?;
+    registry.register(Box::new(cache_lookups.clone()))?;
+    registry.register(Box::new(group_lookups.clone()))?;
+    registry.register(Box::new(tokens_issued.clone()))?;
+    registry.register(Box::new(tokens_revoked.clone()))?;
+    registry.register(Box::new(audit_events_dropped.clone()))?;
+    registry.register(Box::new(pool_connections.clone()))?;
+    registry.register(Box::new(pool_idle_connections.clone()))?;
+
+    Ok(Metrics {
+      registry,
+      token_reviews,
+      request_duration,
+      authenticator_results,
+      authenticator_duration,
+      cache_lookups,
+      group_lookups,
+      tokens_issued,
+      tokens_revoked,
+      audit_events_dropped,
+      pool_connections,
+      pool_idle_connections
+    })
+  }
+
+  /// Counts a TokenReview outcome.
+  ///
+  /// # Arguments
+  /// * `outcome` - authenticated, denied or error.
+  /// * `reason`  - Short machine readable reason.
+  pub fn token_review(&self, outcome: &str, reason: &str) {
+    self.token_reviews.with_label_values(&[outcome, reason]).inc();
+  }
+
+  /// Records the answer of one authenticator in the chain.
+  ///
+  /// # Arguments
+  /// * `authenticator` - Name of the authenticator.
+  /// * `result`        - authenticated, denied, not_mine or error.
+  /// * `elapsed`       - Time the authenticator took.
+  pub fn authenticator_step(&self, authenticator: &str, result: &str, elapsed: Duration) {
+    self.authenticator_results.with_label_values(&[authenticator, result]).inc();
+    self.authenticator_duration.with_label_values(&[authenticator]).observe(seconds(elapsed));
+  }
+
+  /// Counts a decision cache lookup.
+  ///
+  /// # Arguments
+  /// * `hit` - Whether a decision was found.
+  pub fn cache_lookup(&self, hit: bool) {
+    self.cache_lookups.with_label_values(&[if hit { "hit" } else { "miss" }]).inc();
+  }
+
+  /// Counts an LDAP group lookup.
+  ///
+  /// # Arguments
+  /// * `result` - cached, found, not_found, error or skipped.
+  pub fn group_lookup(&self, result: &str) {
+    self.group_lookups.with_label_values(&[result]).inc();
+  }
+
+  /// Counts audit events that were dropped.
+  ///
+  /// # Arguments
+  /// * `reason` - queue_full or write_error.
+  /// * `count`  - Number of events dropped.
+  pub fn audit_event_dropped(&self, reason: &str, count: usize) {
+    self.audit_events_dropped.with_label_values(&[reason]).inc_by(count as i64);
+  }
+
+  /// Renders every metric in the Prometheus text format.
+  /// Pool gauges are sampled at scrape time.
+  ///
+  /// # Arguments
+  /// * `database` - Database whose pool is reported.
+  pub fn render(&self, database: &Database) -> Fallible<String> {
+    let state = database.pool.state();
+    self.pool_connections.set(i64::from(state.connections));
+    self.pool_idle_connections.set(i64::from(state.idle_connections));
+
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+  }
+}
+
+/// Middleware recording the latency of every request, labelled with the pattern of the resource that matched it.
+/// Resources name their pattern with `RouteLabel`, requests reaching none of them are labelled `unmatched`.
+pub struct RequestMetrics(Metrics);
+
+impl RequestMetrics {
+  /// Creates the middleware.
+  ///
+  /// # Arguments
+  /// * `metrics` - Metrics to record into.
+  pub fn new(metrics: Metrics) -> Self {
+    RequestMetrics(metrics)
+  }
+}
+
+impl<S, B> Transform<S> for RequestMetrics
+  where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody {
+
+  type Request = ServiceRequest;
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type InitError = ();
+  type Transform = RequestMetricsMiddleware<S>;
+  type Future = FutureResult<Self::Transform, Self::InitError>;
+
+  fn new_transform(&self, service: S) -> Self::Future {
+    ok(RequestMetricsMiddleware { service, metrics: self.0.clone() })
+  }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+  service: S,
+  metrics: Metrics
+}
+
+impl<S, B> Service for RequestMetricsMiddleware<S>
+  where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody {
+
+  type Request = ServiceRequest;
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error>>;
+
+  fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+    self.service.poll_ready()
+  }
+
+  fn call(&mut self, req: ServiceRequest) -> Self::Future {
+    let started = Instant::now();
+    let method  = req.method().to_string();
+    let metrics = self.metrics.clone();
+
+    Box::new(self.service.call(req).then(move |res| {
+      // Errors that were not turned into a response never reached a resource
+      let (route, status) = match res {
+        Ok(ref response) => (route_label(response.request()), response.status().as_u16()),
+        Err(_)           => (UNMATCHED_ROUTE.to_owned(), 500)
+      };
+
+      metrics.request_duration
+        .with_label_values(&[&method, &route, &status.to_string()])
+        .observe(seconds(started.elapsed()));
+      res
+    }))
+  }
+}
+
+/// Middleware naming the pattern of the resource it wraps (eg `/api/tokens/{id}`, scope included) for `RequestMetrics`.
+/// Wrap it last so requests refused by the resource's other middleware are labelled too.
+pub struct RouteLabel(&'static str);
+
+impl RouteLabel {
+  /// Creates the middleware.
+  ///
+  /// # Arguments
+  /// * `pattern` - Full pattern of the wrapped resource.
+  pub fn new(pattern: &'static str) -> Self {
+    RouteLabel(pattern)
+  }
+}
+
+impl<S, B> Transform<S> for RouteLabel
+  where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static {
+
+  type Request = ServiceRequest;
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type InitError = ();
+  type Transform = RouteLabelMiddleware<S>;
+  type Future = FutureResult<Self::Transform, Self::InitError>;
+
+  fn new_transform(&self, service: S) -> Self::Future {
+    ok(RouteLabelMiddleware { service, pattern: self.0 })
+  }
+}
+
+pub struct RouteLabelMiddleware<S> {
+  service: S,
+  pattern: &'static str
+}
+
+impl<S, B> Service for RouteLabelMiddleware<S>
+  where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static {
+
+  type Request = ServiceRequest;
+  type Response = ServiceResponse<B>;
+  type Error = Error;
+  type Future = S::Future;
+
+  fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+    self.service.poll_ready()
+  }
+
+  fn call(&mut self, req: ServiceRequest) -> Self::Future {
+    req.extensions_mut().insert(MatchedRoute(self.pattern));
+    self.service.call(req)
+  }
+}
+
+/// A duration in fractional seconds, as Prometheus expects.
+fn seconds(duration: Duration) -> f64 {
+  duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+/// Pattern of the resource that matched a request, or `unmatched`.
+fn route_label(req: &HttpRequest) -> String {
+  req.extensions().get::<MatchedRoute>().map_or(UNMATCHED_ROUTE, |route| route.0).to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use actix_web::{test, web, App, HttpResponse};
+  use super::*;
+
+  /// Requests that took each route label.
+  fn requests(metrics: &Metrics, method: &str, route: &str, status: &str) -> u64 {
+    metrics.request_duration.with_label_values(&[method, route, status]).get_sample_count()
+  }
+
+  speculate! {
+    it "labels requests with the pattern of the matched resource" {
+      let metrics = Metrics::new().unwrap();
+      let mut app = test::init_service(
+        App::new()
+          .wrap(RequestMetrics::new(metrics.clone()))
+          .service(
+            web::scope("/api")
+              .service(
+                web::resource("/users/{user_id}/tokens")
+                  .route(web::delete().to(|| HttpResponse::Ok()))
+                  .wrap(RouteLabel::new("/api/users/{user_id}/tokens"))
+              )
+              .service(
+                web::resource("/authenticate")
+                  .route(web::post().to(|| HttpResponse::Unauthorized()))
+                  .wrap(RouteLabel::new("/api/authenticate"))
+              )
+          )
+      );
+
+      // A parameter equal to a literal segment of the pattern must not confuse the label
+      for uri in &["/api/users/kitty/tokens", "/api/users/tokens/tokens"] {
+        test::call_service(&mut app, test::TestRequest::delete().uri(uri).to_request());
+      }
+      test::call_service(&mut app, test::TestRequest::post().uri("/api/authenticate").to_request());
+      test::call_service(&mut app, test::TestRequest::get().uri("/api/authenticate").to_request());
+      test::call_service(&mut app, test::TestRequest::get().uri("/api/users/kitty").to_request());
+
+      assert_eq!(requests(&metrics, "DELETE", "/api/users/{user_id}/tokens", "200"), 2);
+      assert_eq!(requests(&metrics, "POST", "/api/authenticate", "401"), 1);
+      assert_eq!(requests(&metrics, "GET", "/api/authenticate", "405"), 1);
+      assert_eq!(requests(&metrics, "GET", UNMATCHED_ROUTE, "404"), 1);
+    }
+
+    it "renders registered metrics" {
+      let metrics = Metrics::new().unwrap();
+      metrics.token_review("denied", "invalid_token");
+
+      let mut buffer = Vec::new();
+      TextEncoder::new().encode(&metrics.registry.gather(), &mut buffer).unwrap();
+      let text = String::from_utf8(buffer).unwrap();
+      assert!(text.contains("heimdallr_token_reviews_total{outcome=\"denied\",reason=\"invalid_token\"} 1"));
+    }
+  }
+}
@@ -1,14 +1,27 @@
-use openssl::ssl::{SslMethod, SslAcceptor, SslAcceptorBuilder, SslFiletype};
+use openssl::ssl::{SslMethod, SslAcceptor, SslAcceptorBuilder};
 use actix_web::{middleware::{Logger, cors::Cors}, App, HttpServer, web};
 use failure::Fallible;
 use std::io;
 
 use crate::db::Database;
-use crate::settings::Settings;
+use crate::authenticators::Chain;
+use crate::settings::{Settings, TLSConfig};
+use crate::signing::KeyRing;
+use self::admin::AdminAuth;
+use self::audit::AuditLog;
+use self::metrics::{RequestMetrics, RouteLabel};
+use self::tls::Certificates;
 
+mod admin;
 mod api;
+mod audit;
+mod cache;
 mod errors;
+mod metrics;
+mod tls;
+pub use cache::DecisionCache;
 pub use errors::HttpError;
+pub use metrics::Metrics;
 
 /// HTTP Server object.
 pub struct Server {
@@ -25,21 +38,101 @@ impl Server {
 
     // Initialize the database connection
     let database = Database::from_settings(&settings)?;
-    
+    database.ensure_schema(settings.database.auto_migrate)?;
+
+    // Load the token signing keys, refusing to start without one
+    let keys = KeyRing::from_settings(&settings, &database)?;
+
+    // Shared by every worker so counters add up across threads
+    let metrics = Metrics::new()?;
+    let cache   = DecisionCache::from_settings(&settings.cache);
+    let audit   = AuditLog::from_settings(&settings.audit, &database, &metrics)?;
+
+    // Authenticators asked in turn by the TokenReview endpoint
+    let chain = Chain::from_settings(&settings, &database, &keys, &metrics)?;
+
+    // Guards the endpoints issuing and revoking tokens and the audit log
+    let admin = AdminAuth::from_settings(&settings.admin, &keys, &database);
+
     let server = HttpServer::new(move || {
       App::new()
         .data(database.clone())
+        .data(keys.clone())
+        .data(metrics.clone())
+        .data(cache.clone())
+        .data(chain.clone())
+        .data(audit.clone())
+        .wrap(RequestMetrics::new(metrics.clone()))
         .wrap(Logger::default())
         .wrap(Cors::default())
+        .service(
+          web::resource("/metrics")
+            .route(web::get().to(api::metrics))
+            .wrap(RouteLabel::new("/metrics"))
+        )
+        .service(
+          web::scope("/.well-known")
+            .service(
+              web::resource("/jwks.json")
+                .route(web::get().to(api::jwks))
+                .wrap(RouteLabel::new("/.well-known/jwks.json"))
+            )
+            .service(
+              web::resource("/openid-configuration")
+                .route(web::get().to(api::openid_configuration))
+                .wrap(RouteLabel::new("/.well-known/openid-configuration"))
+            )
+        )
         .service(
           web::scope("/api")
             .service(
               web::resource("/healthz")
                 .route(web::get().to_async(api::healthz))
+                .wrap(RouteLabel::new("/api/healthz"))
+            )
+            .service(
+              web::resource("/livez")
+                .route(web::get().to_async(api::healthz))
+                .wrap(RouteLabel::new("/api/livez"))
+            )
+            .service(
+              web::resource("/readyz")
+                .route(web::get().to_async(api::readyz))
+                .wrap(RouteLabel::new("/api/readyz"))
             )
             .service(
               web::resource("/authenticate")
                 .route(web::post().to_async(api::authenticate))
+                .wrap(RouteLabel::new("/api/authenticate"))
+            )
+            .service(
+              web::resource("/authorize")
+                .route(web::post().to_async(api::authorize))
+                .wrap(RouteLabel::new("/api/authorize"))
+            )
+            .service(
+              web::resource("/audit")
+                .route(web::get().to_async(api::audit))
+                .wrap(admin.clone())
+                .wrap(RouteLabel::new("/api/audit"))
+            )
+            .service(
+              web::resource("/tokens")
+                .route(web::post().to_async(api::create_token))
+                .wrap(admin.clone())
+                .wrap(RouteLabel::new("/api/tokens"))
+            )
+            .service(
+              web::resource("/tokens/{id}")
+                .route(web::delete().to_async(api::revoke_token))
+                .wrap(admin.clone())
+                .wrap(RouteLabel::new("/api/tokens/{id}"))
+            )
+            .service(
+              web::resource("/users/{user_id}/tokens")
+                .route(web::delete().to_async(api::revoke_user_tokens))
+                .wrap(admin.clone())
+                .wrap(RouteLabel::new("/api/users/{user_id}/tokens"))
             )
         )
     });
@@ -47,7 +140,7 @@ impl Server {
     if settings.inbound_listener.tls.enabled {
       server.bind_ssl(
         &settings.inbound_listener.address,
-        Self::build_tls(&settings.inbound_listener.tls.private_key, &settings.inbound_listener.tls.cert)?
+        Self::build_tls(&settings.inbound_listener.tls)?
       )?.start();
     }
     else {
@@ -63,13 +156,21 @@ impl Server {
   }
 
   /// Creates an SSL Acceptor object.
+  /// The certificate is reloaded when its files change or on SIGHUP, without restarting the server.
   /// 
   /// # Arguments
   /// * `tls` - TLS configuration settings.
-  fn build_tls(private_key: &str, cert: &str) -> Fallible<SslAcceptorBuilder> {
+  fn build_tls(tls: &TLSConfig) -> Fallible<SslAcceptorBuilder> {
     let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
-    builder.set_private_key_file(private_key, SslFiletype::PEM)?;
-    builder.set_certificate_chain_file(cert)?;
+    tls::configure(&mut builder, tls)?;
+
+    if let Some(ref client_ca) = tls.client_ca {
+      info!("Verifying client certificates against {} ({:?})", client_ca, tls.client_auth);
+    }
+
+    // Every handshake picks up the latest certificate, SNI or not
+    let certificates = Certificates::watch(tls)?;
+    builder.set_servername_callback(move |ssl, _| certificates.apply(ssl));
     Ok(builder)
   }
 }
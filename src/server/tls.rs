@@ -0,0 +1,186 @@
+use openssl::ssl::{SniError, SslContext, SslContextBuilder, SslFiletype, SslMethod, SslRef, SslVerifyMode};
+use openssl::x509::{X509Name, X509Ref};
+use openssl::nid::Nid;
+use failure::{Fallible, format_err};
+use std::fs;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::settings::{ClientAuth, TLSConfig};
+
+/// Seconds between checks of the certificate files when `reload_interval` is not set.
+const DEFAULT_RELOAD_INTERVAL: u64 = 30;
+
+/// How often the watcher wakes up to look for a SIGHUP.
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Loads the serving certificate and key, and verification of client certificates if configured.
+///
+/// # Arguments
+/// * `builder` - Context to configure.
+/// * `tls`     - TLS configuration settings.
+pub fn configure(builder: &mut SslContextBuilder, tls: &TLSConfig) -> Fallible<()> {
+  builder.set_private_key_file(&tls.private_key, SslFiletype::PEM)?;
+  builder.set_certificate_chain_file(&tls.cert)?;
+  builder.check_private_key()?;
+
+  let client_ca = match tls.client_ca {
+    Some(ref client_ca) => client_ca,
+    None if tls.allowed_client_cns.is_empty() => return Ok(()),
+    None => return Err(format_err!("allowed_client_cns requires a client_ca"))
+  };
+
+  builder.set_ca_file(client_ca)?;
+  builder.set_client_ca_list(X509Name::load_client_ca_file(client_ca)?);
+  builder.set_session_id_context(b"heimdallr")?;
+
+  let mode = match tls.client_auth {
+    ClientAuth::Required => SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+    ClientAuth::Optional => SslVerifyMode::PEER
+  };
+
+  // Only the leaf certificate names the client, the rest of the chain is left to OpenSSL.
+  // The callback runs whenever a certificate is presented, so optional mode still enforces the allowed names.
+  let allowed = tls.allowed_client_cns.clone();
+  builder.set_verify_callback(mode, move |verified, ctx| {
+    if !verified || allowed.is_empty() || ctx.error_depth() != 0 {
+      return verified;
+    }
+
+    match ctx.current_cert().and_then(common_name) {
+      Some(ref name) if allowed.contains(name) => true,
+      name => {
+        warn!("Rejecting client certificate with CN {:?}", name);
+        false
+      }
+    }
+  });
+
+  Ok(())
+}
+
+/// The serving certificate, reloaded from disk when its files change or the process receives SIGHUP.
+/// New connections are switched to the latest certificate during the handshake, established ones are left alone.
+#[derive(Clone)]
+pub struct Certificates {
+  current: Arc<RwLock<SslContext>>
+}
+
+impl Certificates {
+  /// Loads the certificate and starts watching its files.
+  ///
+  /// # Arguments
+  /// * `tls` - TLS configuration settings.
+  pub fn watch(tls: &TLSConfig) -> Fallible<Self> {
+    let certificates = Certificates { current: Arc::new(RwLock::new(load(tls)?)) };
+
+    let hangup = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::SIGHUP, hangup.clone())?;
+
+    let watcher = Watcher::new(tls, certificates.clone(), hangup);
+    thread::Builder::new()
+      .name("tls-reload".to_owned())
+      .spawn(move || watcher.run())?;
+
+    Ok(certificates)
+  }
+
+  /// Switches a connection to the latest certificate, meant to be called from the servername callback.
+  ///
+  /// # Arguments
+  /// * `ssl` - Connection being accepted.
+  pub fn apply(&self, ssl: &mut SslRef) -> Result<(), SniError> {
+    let current = self.current.read().unwrap_or_else(|e| e.into_inner());
+    ssl.set_ssl_context(&current).map_err(|e| {
+      error!("Unable to switch to the reloaded certificate: {}", e);
+      SniError::ALERT_FATAL
+    })
+  }
+
+  fn replace(&self, context: SslContext) {
+    *self.current.write().unwrap_or_else(|e| e.into_inner()) = context;
+  }
+}
+
+/// Background loop reloading certificates.
+struct Watcher {
+  tls: TLSConfig,
+  certificates: Certificates,
+  hangup: Arc<AtomicBool>,
+  interval: Option<Duration>,
+  modified: (Option<SystemTime>, Option<SystemTime>)
+}
+
+impl Watcher {
+  fn new(tls: &TLSConfig, certificates: Certificates, hangup: Arc<AtomicBool>) -> Self {
+    let interval = match tls.reload_interval.unwrap_or(DEFAULT_RELOAD_INTERVAL) {
+      0       => None,
+      seconds => Some(Duration::from_secs(seconds))
+    };
+
+    Watcher { tls: tls.clone(), certificates, hangup, interval, modified: modified(tls) }
+  }
+
+  fn run(mut self) {
+    let mut checked = Instant::now();
+
+    loop {
+      thread::sleep(SIGNAL_POLL_INTERVAL);
+
+      if self.hangup.swap(false, Ordering::SeqCst) {
+        info!("Received SIGHUP, reloading the TLS certificate");
+        self.reload();
+        continue;
+      }
+
+      match self.interval {
+        Some(interval) if checked.elapsed() >= interval => checked = Instant::now(),
+        _ => continue
+      }
+
+      if modified(&self.tls) != self.modified {
+        info!("TLS certificate files changed, reloading");
+        self.reload();
+      }
+    }
+  }
+
+  /// Keeps serving the previous certificate when the new one cannot be loaded (eg only one file was replaced yet),
+  /// and tries again at the next check.
+  fn reload(&mut self) {
+    let modified = modified(&self.tls);
+
+    match load(&self.tls) {
+      Ok(context) => {
+        self.certificates.replace(context);
+        self.modified = modified;
+        info!("Reloaded TLS certificate from {}", self.tls.cert);
+      },
+      Err(e) => error!("Unable to reload TLS certificate, still serving the previous one: {}", e)
+    }
+  }
+}
+
+/// Builds a context holding the certificate.
+fn load(tls: &TLSConfig) -> Fallible<SslContext> {
+  let mut builder = SslContextBuilder::new(SslMethod::tls())?;
+  configure(&mut builder, tls)?;
+  Ok(builder.build())
+}
+
+/// Modification times of the key and certificate files.
+fn modified(tls: &TLSConfig) -> (Option<SystemTime>, Option<SystemTime>) {
+  let modified = |path: &str| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+  (modified(&tls.private_key), modified(&tls.cert))
+}
+
+/// Common name of a certificate's subject.
+fn common_name(cert: &X509Ref) -> Option<String> {
+  cert.subject_name()
+    .entries_by_nid(Nid::COMMONNAME)
+    .next()
+    .and_then(|entry| entry.data().as_utf8().ok())
+    .map(|name| name.to_string())
+}
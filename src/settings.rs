@@ -1,11 +1,38 @@
 use config::{ConfigError, Config, File, Environment};
+use jsonwebtoken::Algorithm;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 
 #[derive(Debug, Deserialize)]
 pub struct Settings {
   pub inbound_listener: Listener,
-  pub database: Database
+  pub database: Database,
+  pub signing: Signing,
+
+  #[serde(default)]
+  pub logging: Logging,
+
+  #[serde(default)]
+  pub cache: Cache,
+
+  /// Directory searched for the groups of authenticated users, no lookup is made when unset.
+  pub ldap: Option<Ldap>,
+
+  #[serde(default)]
+  pub admin: Admin,
+
+  #[serde(default)]
+  pub audit: Audit,
+
+  /// Authenticators asked in turn to review a token, the first to authenticate or deny it decides.
+  #[serde(default = "default_authenticators")]
+  pub authenticators: Vec<AuthenticatorConfig>
+}
+
+fn default_authenticators() -> Vec<AuthenticatorConfig> {
+  vec![AuthenticatorConfig::Jwt(ClaimMapping::default())]
 }
 
 #[derive(Debug, Deserialize)]
@@ -15,7 +42,11 @@ pub struct Database {
   pub port: Option<i32>,
   pub username: String,
   pub password: String,
-  pub pool: Option<usize>
+  pub pool: Option<usize>,
+
+  /// Apply pending migrations at startup instead of refusing to start.
+  #[serde(default)]
+  pub auto_migrate: bool
 }
 
 #[derive(Debug, Deserialize)]
@@ -28,11 +59,314 @@ pub struct Listener {
   pub tls: TLSConfig
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 pub struct TLSConfig {
   pub enabled: bool,
   pub private_key: String,
-  pub cert: String
+  pub cert: String,
+
+  /// Path to a PEM bundle of the CAs that sign client certificates, turns on client certificate verification.
+  pub client_ca: Option<String>,
+
+  /// Whether clients must present a certificate once `client_ca` is set.
+  #[serde(default)]
+  pub client_auth: ClientAuth,
+
+  /// Common names accepted in client certificates, any certificate signed by `client_ca` is accepted when empty.
+  #[serde(default)]
+  pub allowed_client_cns: Vec<String>,
+
+  /// Seconds between checks of `private_key` and `cert` for changes (default 30, `0` only reloads on SIGHUP).
+  pub reload_interval: Option<u64>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientAuth {
+  /// Refuse connections without a valid client certificate.
+  Required,
+
+  /// Verify client certificates when presented, but also accept connections without one.
+  Optional
+}
+
+impl Default for ClientAuth {
+  fn default() -> ClientAuth {
+    ClientAuth::Required
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Signing {
+  /// Value of the `iss` claim of issued tokens, also published in the OpenID discovery document.
+  pub issuer: Option<String>,
+
+  /// Key used to issue (and verify) tokens.
+  #[serde(flatten)]
+  pub key: KeyConfig,
+
+  /// Keys that no longer sign, still accepted and published so their tokens can be verified until they expire.
+  #[serde(default)]
+  pub previous: Vec<KeyConfig>,
+
+  /// Also load keys from the `signing_keys` table.
+  #[serde(default)]
+  pub database_keys: bool,
+
+  /// Base64 encoded 256 bit key encrypting the private keys stored in `signing_keys` (eg `openssl rand -base64 32`).
+  pub database_key: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyConfig {
+  /// JWT algorithm (HS256/384/512, RS256/384/512 or ES256/384).
+  pub algorithm: Algorithm,
+
+  /// Key identifier placed in the `kid` header, defaults to the RFC 7638 thumbprint of the public key.
+  pub kid: Option<String>,
+
+  /// Shared secret, only used by the HS* algorithms.
+  pub secret: Option<String>,
+
+  /// Path to a PEM private key, needed to issue tokens.
+  pub private_key: Option<String>,
+
+  /// Path to a PEM public key, enough to verify tokens.
+  pub public_key: Option<String>,
+
+  /// When the key stops being accepted (RFC 3339), set it past the expiry of the last token it signed.
+  pub retire_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Logging {
+  /// Default level (off, error, warn, info, debug or trace).
+  pub level: String,
+
+  /// Levels for specific targets (module paths), overriding `level`.
+  pub targets: BTreeMap<String, String>,
+
+  /// Output format of every sink.
+  pub format: LogFormat,
+
+  /// Write to stdout.
+  pub stdout: bool,
+
+  /// Path of a file to append to as well, nothing is written to disk when unset.
+  pub file: Option<String>
+}
+
+impl Default for Logging {
+  fn default() -> Logging {
+    Logging {
+      level: "info".to_owned(),
+      targets: BTreeMap::new(),
+      format: LogFormat::Text,
+      stdout: true,
+      file: None
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+  /// Human readable lines.
+  Text,
+
+  /// One JSON object per line.
+  Json
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Cache {
+  /// Cache successful TokenReview decisions.
+  pub enabled: bool,
+
+  /// Seconds a decision is kept, never past the expiry of its token.
+  pub ttl: u64,
+
+  /// Most decisions kept at once.
+  pub capacity: usize
+}
+
+impl Default for Cache {
+  fn default() -> Cache {
+    Cache { enabled: true, ttl: 30, capacity: 10_000 }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Audit {
+  /// Most events waiting to be stored, further events are dropped (and counted) until the writer catches up.
+  pub queue_size: usize,
+
+  /// Most events stored per insert.
+  pub batch_size: usize
+}
+
+impl Default for Audit {
+  fn default() -> Audit {
+    Audit { queue_size: 10_000, batch_size: 100 }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Admin {
+  /// Scope a bearer token must carry to use the administration endpoints (issuing tokens, revoking, audit log).
+  pub scope: String
+}
+
+impl Default for Admin {
+  fn default() -> Admin {
+    Admin { scope: "heimdallr:admin".to_owned() }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Ldap {
+  /// `ldap://` or `ldaps://` URL of the directory.
+  pub url: String,
+
+  /// DN to bind as, the search is anonymous when unset.
+  pub bind_dn: Option<String>,
+  pub bind_password: Option<String>,
+
+  /// Entry under which users are searched.
+  pub base_dn: String,
+
+  /// Filter finding a user, `{username}` is replaced with the escaped username.
+  #[serde(default = "default_ldap_user_filter")]
+  pub user_filter: String,
+
+  /// Attribute of user entries listing their groups, values that are DNs are reduced to their CN.
+  #[serde(default = "default_ldap_group_attribute")]
+  pub group_attribute: String,
+
+  /// Prepended to every group found.
+  pub groups_prefix: Option<String>,
+
+  /// Seconds a whole lookup (connecting, binding and searching) may take.
+  #[serde(default = "default_ldap_timeout")]
+  pub timeout: u64,
+
+  /// Seconds the directory is left alone after a failed lookup, users get no directory groups meanwhile.
+  #[serde(default = "default_ldap_retry_after")]
+  pub retry_after: u64,
+
+  /// Seconds the groups of a user are kept.
+  #[serde(default = "default_ldap_cache_ttl")]
+  pub cache_ttl: u64,
+
+  /// Most users whose groups are kept at once, `0` disables the cache.
+  #[serde(default = "default_ldap_cache_capacity")]
+  pub cache_capacity: usize
+}
+
+fn default_ldap_user_filter() -> String {
+  "(uid={username})".to_owned()
+}
+
+fn default_ldap_group_attribute() -> String {
+  "memberOf".to_owned()
+}
+
+fn default_ldap_timeout() -> u64 {
+  5
+}
+
+fn default_ldap_retry_after() -> u64 {
+  30
+}
+
+fn default_ldap_cache_ttl() -> u64 {
+  300
+}
+
+fn default_ldap_cache_capacity() -> usize {
+  10_000
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthenticatorConfig {
+  /// Tokens issued by this service.
+  Jwt(ClaimMapping),
+
+  /// Tokens listed, hashed, in a file in the format of the kube-apiserver's `--token-auth-file`.
+  StaticTokens {
+    /// Path of the CSV file, or YAML when it ends in `.yaml` or `.yml`.
+    path: String,
+
+    /// Seconds between checks of the file for changes (default 10).
+    reload_interval: Option<u64>
+  },
+
+  /// ID tokens of an upstream OpenID Connect provider.
+  Oidc(OidcConfig)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct OidcConfig {
+  /// Expected `iss` claim, tokens of other issuers are left to the next authenticator.
+  pub issuer: String,
+
+  /// Client ID tokens must be issued to, as their `aud` claim.
+  pub client_id: String,
+
+  /// Local JWKS file holding the provider's keys.
+  pub jwks_file: Option<String>,
+
+  /// URL of the provider's JWKS, used when no `jwks_file` is set.
+  pub jwks_url: Option<String>,
+
+  /// Seconds between reloads of the JWKS (default 300).
+  pub jwks_refresh_interval: Option<u64>,
+
+  /// How claims become the user, the username defaults to the `sub` claim prefixed with `<issuer>#`
+  /// (no prefix when the username claim is `email`).
+  #[serde(flatten)]
+  pub mapping: ClaimMapping
+}
+
+/// Rules turning the claims of a verified token into the user returned by the TokenReview.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ClaimMapping {
+  /// Claim holding the username, each authenticator has its own default.
+  pub username_claim: Option<String>,
+
+  /// Prepended to the username, `-` disables the authenticator's default prefix.
+  pub username_prefix: Option<String>,
+
+  /// Claims whose values are added to the groups, each a string or a list of strings.
+  #[serde(alias = "groups_claim", deserialize_with = "one_or_many")]
+  pub groups_claims: Vec<String>,
+
+  /// Prepended to groups taken from claims.
+  pub groups_prefix: Option<String>,
+
+  /// Claims copied to `extra`, under their own name.
+  pub extra_claims: Vec<String>
+}
+
+/// Accepts either a single string or a list of strings.
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error> where D: Deserializer<'de> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum OneOrMany {
+    One(String),
+    Many(Vec<String>)
+  }
+
+  Ok(match OneOrMany::deserialize(deserializer)? {
+    OneOrMany::One(value)   => vec![value],
+    OneOrMany::Many(values) => values
+  })
 }
 
 impl Settings {
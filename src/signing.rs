@@ -0,0 +1,616 @@
+use jsonwebtoken::{decode as jwt_decode, decode_header, encode as jwt_encode, Algorithm, Header, Validation, TokenData};
+use jsonwebtoken::errors::{Error as JwtError, ErrorKind as JwtErrorKind};
+use openssl::{bn::{BigNum, BigNumContext}, ec::{EcKey, PointConversionForm}, nid::Nid, pkey::{PKey, Private, Public}, rsa::Rsa, sha::sha256};
+use openssl::{rand::rand_bytes, symm::{decrypt_aead, encrypt_aead, Cipher}};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use failure::{Fallible, format_err};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+
+use crate::settings;
+use crate::db::Database;
+use crate::models::StoredSigningKey;
+
+/// Issuer used when none is configured, matches the default `iss` claim.
+const DEFAULT_ISSUER: &str = "heimdallr";
+
+/// Prefix of private keys encrypted for the `signing_keys` table, naming the cipher.
+const ENCRYPTED_PREFIX: &str = "aes256gcm:";
+
+/// Bytes of the random nonce starting each encrypted private key.
+const NONCE_LENGTH: usize = 12;
+
+/// Bytes of the authentication tag ending each encrypted private key.
+const TAG_LENGTH: usize = 16;
+
+/// Every key this service signs with or still accepts.
+/// Tokens are verified with the key named by their `kid` header, so tokens signed by a previous key keep working
+/// after a rotation until that key's `retire_at` has passed.
+#[derive(Clone)]
+pub struct KeyRing {
+  /// Value of the `iss` claim of issued tokens.
+  pub issuer: String,
+
+  /// Key used to issue tokens.
+  pub active: SigningKey,
+
+  /// Keys that no longer sign but are still accepted and published until they retire.
+  pub previous: Vec<SigningKey>
+}
+
+impl KeyRing {
+  /// Loads the key ring using settings, adding keys from the `signing_keys` table when enabled.
+  /// An active key stored in the database takes over from the configured one, which then only verifies.
+  ///
+  /// # Arguments
+  /// * `settings` - Settings to use.
+  /// * `database` - Database to load stored keys from.
+  pub fn from_settings(settings: &settings::Settings, database: &Database) -> Fallible<Self> {
+    let mut active   = SigningKey::from_config(&settings.signing.key)?;
+    let mut previous = settings.signing.previous.iter()
+      .map(SigningKey::from_config)
+      .collect::<Fallible<Vec<_>>>()?;
+
+    if settings.signing.database_keys {
+      let storage = StorageKey::from_settings(&settings.signing)?;
+      let conn    = database.pool.get()?;
+      for stored in StoredSigningKey::unretired(&conn)? {
+        let key = SigningKey::from_stored(&stored, storage.as_ref())?;
+        if stored.active {
+          previous.push(std::mem::replace(&mut active, key));
+        }
+        else {
+          previous.push(key);
+        }
+      }
+    }
+
+    if active.is_retired() {
+      return Err(format_err!("active signing key {} is retired", active.kid));
+    }
+
+    let ring = KeyRing {
+      issuer: settings.signing.issuer.to_owned().unwrap_or_else(|| DEFAULT_ISSUER.to_owned()),
+      active,
+      previous
+    };
+    ring.check_kids()?;
+
+    info!("Signing with key {}, accepting {} previous key(s)", ring.active.kid, ring.previous.len());
+    Ok(ring)
+  }
+
+  /// Refuses rings where accepted keys share a `kid`, as tokens would be checked against whichever comes first.
+  /// Shared secrets all default to the same `kid`, so this catches several HS* keys without one.
+  fn check_kids(&self) -> Fallible<()> {
+    let mut kids = HashSet::new();
+    for key in self.keys() {
+      if !kids.insert(key.kid.as_str()) {
+        return Err(format_err!("several signing keys use kid {:?}, give each one its own kid", key.kid));
+      }
+    }
+    Ok(())
+  }
+
+  /// Keys that are currently accepted, starting with the active one.
+  pub fn keys(&self) -> impl Iterator<Item = &SigningKey> {
+    std::iter::once(&self.active)
+      .chain(self.previous.iter())
+      .filter(|key| !key.is_retired())
+  }
+
+  /// Finds an accepted key by its identifier.
+  ///
+  /// # Arguments
+  /// * `kid` - Key identifier.
+  pub fn find(&self, kid: &str) -> Option<&SigningKey> {
+    self.keys().find(|key| key.kid == kid)
+  }
+
+  /// Verifies a JWT with the key named by its `kid` header and decodes its claims.
+  /// Tokens without a `kid` were issued before key identifiers existed and are checked against the active key.
+  ///
+  /// # Arguments
+  /// * `token` - Encoded JWT to verify.
+  pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<TokenData<T>, JwtError> {
+    let key = match decode_header(token)?.kid {
+      Some(kid) => self.find(&kid).ok_or_else(|| JwtError::from(JwtErrorKind::InvalidSignature))?,
+      None      => &self.active
+    };
+    key.verify(token)
+  }
+
+  /// Signs claims with the active key.
+  ///
+  /// # Arguments
+  /// * `claims` - Claims to sign.
+  pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, JwtError> {
+    self.active.sign(claims)
+  }
+
+  /// Public keys of every accepted key, in JWK Set form.
+  pub fn jwks(&self) -> JwkSet {
+    JwkSet { keys: self.keys().filter_map(SigningKey::jwk).collect() }
+  }
+
+  /// Algorithms of every accepted key.
+  pub fn algorithms(&self) -> Vec<Algorithm> {
+    let mut algorithms: Vec<Algorithm> = Vec::new();
+    for key in self.keys() {
+      if !algorithms.contains(&key.algorithm) {
+        algorithms.push(key.algorithm);
+      }
+    }
+    algorithms
+  }
+}
+
+/// Key material used to sign and verify tokens.
+/// Asymmetric keys only need the public half to verify, so verifiers can run without the signing secret.
+#[derive(Clone)]
+pub struct SigningKey {
+  pub algorithm: Algorithm,
+
+  /// Key identifier, placed in the `kid` header of signed tokens.
+  pub kid: String,
+
+  /// When the key stops being accepted, usually once every token it signed has expired.
+  pub retire_at: Option<DateTime<Utc>>,
+
+  /// Key in the form `jsonwebtoken` signs with (HMAC secret, PKCS#1 DER for RSA, PKCS#8 DER for ECDSA).
+  signing: Option<Vec<u8>>,
+
+  /// Key in the form `jsonwebtoken` verifies with (HMAC secret, PKCS#1 DER for RSA, uncompressed point for ECDSA).
+  verifying: Vec<u8>
+}
+
+impl SigningKey {
+  /// Loads a signing key from its configuration.
+  ///
+  /// # Arguments
+  /// * `config` - Signing key configuration.
+  pub fn from_config(config: &settings::KeyConfig) -> Fallible<Self> {
+    let private_pem = config.private_key.as_ref().map(std::fs::read).transpose()?;
+    let public_pem  = config.public_key.as_ref().map(std::fs::read).transpose()?;
+
+    let mut key = Self::from_pem(config.algorithm, config.secret.as_ref().map(String::as_bytes), private_pem.as_ref().map(Vec::as_slice), public_pem.as_ref().map(Vec::as_slice))?;
+    key.retire_at = config.retire_at;
+    if let Some(ref kid) = config.kid {
+      key.kid = kid.to_owned();
+    }
+    Ok(key)
+  }
+
+  /// Loads a signing key stored in the `signing_keys` table.
+  ///
+  /// # Arguments
+  /// * `stored`  - Stored key.
+  /// * `storage` - Key decrypting the stored private key, needed when there is one.
+  pub fn from_stored(stored: &StoredSigningKey, storage: Option<&StorageKey>) -> Fallible<Self> {
+    let algorithm = algorithm_named(&stored.algorithm)
+      .map_err(|_| format_err!("signing key {} has unknown algorithm {:?}", stored.kid, stored.algorithm))?;
+
+    let private_pem = match (&stored.private_key, storage) {
+      (Some(encrypted), Some(storage)) => Some(storage.decrypt(&stored.kid, encrypted)?),
+      (Some(_), None)                  => return Err(format_err!("signing key {} has a private key but signing.database_key is not set", stored.kid)),
+      (None, _)                        => None
+    };
+
+    let mut key = Self::from_pem(algorithm, None, private_pem.as_ref().map(Vec::as_slice), Some(stored.public_key.as_bytes()))?;
+    key.kid       = stored.kid.to_owned();
+    key.retire_at = stored.retire_at.map(|retire_at| DateTime::from_utc(retire_at, Utc));
+    Ok(key)
+  }
+
+  /// Loads a public key published as a JWK, to verify tokens issued by someone else.
+  /// The algorithm is taken from `alg`, or from the key type and curve when it is absent.
+  ///
+  /// # Arguments
+  /// * `jwk` - Published key.
+  pub fn from_jwk(jwk: &Jwk) -> Fallible<Self> {
+    let member = |value: &Option<String>, name: &str| -> Fallible<Vec<u8>> {
+      let value = value.as_ref().ok_or_else(|| format_err!("JWK {:?} has no {:?} member", jwk.kid, name))?;
+      Ok(base64::decode_config(value, base64::URL_SAFE_NO_PAD)?)
+    };
+
+    let alg = if jwk.alg.is_empty() { None } else { Some(algorithm_named(&jwk.alg)?) };
+    let (algorithm, verifying) = match jwk.kty.as_str() {
+      "RSA" => {
+        let algorithm = alg.unwrap_or(Algorithm::RS256);
+        if ![Algorithm::RS256, Algorithm::RS384, Algorithm::RS512].contains(&algorithm) {
+          return Err(format_err!("JWK {:?} is an RSA key but uses {:?}", jwk.kid, algorithm));
+        }
+
+        let rsa = Rsa::from_public_components(BigNum::from_slice(&member(&jwk.n, "n")?)?, BigNum::from_slice(&member(&jwk.e, "e")?)?)?;
+        (algorithm, rsa.public_key_to_der_pkcs1()?)
+      },
+      "EC" => {
+        let (algorithm, size) = match jwk.crv.as_ref().map(String::as_str) {
+          Some("P-256") => (Algorithm::ES256, 32),
+          Some("P-384") => (Algorithm::ES384, 48),
+          crv => return Err(format_err!("JWK {:?} uses unsupported curve {:?}", jwk.kid, crv))
+        };
+        if alg.map_or(false, |alg| alg != algorithm) {
+          return Err(format_err!("JWK {:?} curve does not match {:?}", jwk.kid, alg));
+        }
+
+        let (x, y) = (member(&jwk.x, "x")?, member(&jwk.y, "y")?);
+        if x.len() != size || y.len() != size {
+          return Err(format_err!("JWK {:?} has coordinates of the wrong size", jwk.kid));
+        }
+
+        // Uncompressed points are 0x04 || x || y
+        let mut point = vec![0x04];
+        point.extend(x);
+        point.extend(y);
+        (algorithm, point)
+      },
+      kty => return Err(format_err!("JWK {:?} has unsupported key type {:?}", jwk.kid, kty))
+    };
+
+    let mut key = SigningKey { algorithm, kid: jwk.kid.to_owned(), retire_at: None, signing: None, verifying };
+    if key.kid.is_empty() {
+      key.kid = key.thumbprint();
+    }
+    Ok(key)
+  }
+
+  /// Loads a signing key from PEM encoded keys (or a shared secret for HS* algorithms).
+  /// The `kid` defaults to the RFC 7638 thumbprint of the public key.
+  ///
+  /// # Arguments
+  /// * `algorithm`   - JWT algorithm the key is used with.
+  /// * `secret`      - Shared secret for HS* algorithms.
+  /// * `private_pem` - PEM private key, needed to issue tokens.
+  /// * `public_pem`  - PEM public key, enough to verify tokens.
+  pub fn from_pem(algorithm: Algorithm, secret: Option<&[u8]>, private_pem: Option<&[u8]>, public_pem: Option<&[u8]>) -> Fallible<Self> {
+    let (signing, verifying) = match algorithm {
+      Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+        let secret = secret
+          .filter(|secret| !secret.is_empty())
+          .ok_or_else(|| format_err!("signing.secret is required for {:?}", algorithm))?;
+
+        (Some(secret.to_vec()), secret.to_vec())
+      },
+      Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+        let signing = match private_pem {
+          Some(pem) => Some(PKey::private_key_from_pem(pem)?.rsa()?),
+          None      => None
+        };
+
+        let verifying = match (public_pem, &signing) {
+          (Some(pem), _)    => PKey::public_key_from_pem(pem)?.rsa()?.public_key_to_der_pkcs1()?,
+          (None, Some(rsa)) => rsa.public_key_to_der_pkcs1()?,
+          (None, None)      => return Err(format_err!("signing.public_key or signing.private_key is required for {:?}", algorithm))
+        };
+
+        (signing.map(|rsa| rsa.private_key_to_der()).transpose()?, verifying)
+      },
+      Algorithm::ES256 | Algorithm::ES384 => {
+        let private_key = match private_pem {
+          Some(pem) => Some(PKey::private_key_from_pem(pem)?),
+          None      => None
+        };
+
+        let ec_key = match (public_pem, &private_key) {
+          (Some(pem), _)    => PKey::public_key_from_pem(pem)?.ec_key()?,
+          (None, Some(key)) => public_ec_key(&key.ec_key()?)?,
+          (None, None)      => return Err(format_err!("signing.public_key or signing.private_key is required for {:?}", algorithm))
+        };
+
+        if ec_key.group().curve_name() != Some(curve(algorithm)) {
+          return Err(format_err!("signing key curve does not match {:?}", algorithm));
+        }
+
+        let mut ctx   = BigNumContext::new()?;
+        let verifying = ec_key.public_key().to_bytes(ec_key.group(), PointConversionForm::UNCOMPRESSED, &mut ctx)?;
+        let signing   = match private_key {
+          Some(key) => Some(pem_to_der(&key.private_key_to_pem_pkcs8()?)?),
+          None      => None
+        };
+
+        (signing, verifying)
+      }
+    };
+
+    let mut key = SigningKey { algorithm, kid: String::new(), retire_at: None, signing, verifying };
+    key.kid = key.thumbprint();
+    Ok(key)
+  }
+
+  /// Whether the key has passed its `retire_at` and must no longer be accepted.
+  pub fn is_retired(&self) -> bool {
+    self.retire_at.map_or(false, |retire_at| retire_at <= Utc::now())
+  }
+
+  /// Whether this key holds the private half and can issue tokens.
+  pub fn can_sign(&self) -> bool {
+    self.signing.is_some()
+  }
+
+  /// Signs claims into an encoded JWT.
+  ///
+  /// # Arguments
+  /// * `claims` - Claims to sign.
+  pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, JwtError> {
+    let key    = self.signing.as_ref().ok_or_else(|| JwtError::from(JwtErrorKind::InvalidAlgorithm))?;
+    let header = Header { kid: Some(self.kid.to_owned()), ..Header::new(self.algorithm) };
+    jwt_encode(&header, claims, key)
+  }
+
+  /// Verifies the signature of a JWT and decodes its claims.
+  ///
+  /// # Arguments
+  /// * `token` - Encoded JWT to verify.
+  pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<TokenData<T>, JwtError> {
+    self.verify_with(token, &Validation::new(self.algorithm))
+  }
+
+  /// Verifies the signature of a JWT and decodes its claims, checking them against custom rules.
+  ///
+  /// # Arguments
+  /// * `token`      - Encoded JWT to verify.
+  /// * `validation` - Claims to check, its `algorithms` should hold the key's algorithm.
+  pub fn verify_with<T: DeserializeOwned>(&self, token: &str, validation: &Validation) -> Result<TokenData<T>, JwtError> {
+    jwt_decode::<T>(token, &self.verifying, validation)
+  }
+
+  /// The public key as a JWK, `None` for shared secrets which must never be published.
+  pub fn jwk(&self) -> Option<Jwk> {
+    let mut jwk = Jwk {
+      kid: self.kid.to_owned(),
+      alg: format!("{:?}", self.algorithm),
+      key_use: "sig".to_owned(),
+      ..Default::default()
+    };
+
+    match self.algorithm {
+      Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => return None,
+      Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+        let rsa = Rsa::public_key_from_der_pkcs1(&self.verifying).ok()?;
+        jwk.kty = "RSA".to_owned();
+        jwk.n   = Some(base64_url(&rsa.n().to_vec()));
+        jwk.e   = Some(base64_url(&rsa.e().to_vec()));
+      },
+      Algorithm::ES256 | Algorithm::ES384 => {
+        // Uncompressed points are 0x04 || x || y
+        let size = (self.verifying.len() - 1) / 2;
+        jwk.kty = "EC".to_owned();
+        jwk.crv = Some(if self.algorithm == Algorithm::ES256 { "P-256" } else { "P-384" }.to_owned());
+        jwk.x   = Some(base64_url(&self.verifying[1..=size]));
+        jwk.y   = Some(base64_url(&self.verifying[size + 1..]));
+      }
+    }
+    Some(jwk)
+  }
+
+  /// RFC 7638 thumbprint of the public key.
+  /// Shared secrets get a fixed identifier, as hashing them would leak material for offline guessing,
+  /// so rings holding several of them must name each one's `kid`.
+  fn thumbprint(&self) -> String {
+    let jwk = match self.jwk() {
+      Some(jwk) => jwk,
+      None      => return "hmac".to_owned()
+    };
+
+    // Members must be in lexicographic order without whitespace
+    let canonical = match jwk.kty.as_str() {
+      "RSA" => format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, jwk.e.unwrap_or_default(), jwk.n.unwrap_or_default()),
+      _     => format!(r#"{{"crv":"{}","kty":"EC","x":"{}","y":"{}"}}"#, jwk.crv.unwrap_or_default(), jwk.x.unwrap_or_default(), jwk.y.unwrap_or_default())
+    };
+    base64_url(&sha256(canonical.as_bytes()))
+  }
+}
+
+/// Key encrypting the private keys stored in the `signing_keys` table, so the database never holds them in the clear.
+/// Keys are sealed with AES-256-GCM and bound to their `kid`, so a sealed key cannot be moved to another row.
+#[derive(Clone)]
+pub struct StorageKey(Vec<u8>);
+
+impl StorageKey {
+  /// Loads the key from settings, `None` when `database_key` is not set.
+  ///
+  /// # Arguments
+  /// * `signing` - Signing settings to use.
+  pub fn from_settings(signing: &settings::Signing) -> Fallible<Option<Self>> {
+    let encoded = match signing.database_key {
+      Some(ref encoded) => encoded,
+      None              => return Ok(None)
+    };
+
+    let key = base64::decode(encoded.trim()).map_err(|_| format_err!("signing.database_key is not valid base64"))?;
+    if key.len() != Cipher::aes_256_gcm().key_len() {
+      return Err(format_err!("signing.database_key must be {} bytes, got {}", Cipher::aes_256_gcm().key_len(), key.len()));
+    }
+    Ok(Some(StorageKey(key)))
+  }
+
+  /// Encrypts a PEM private key for the `private_key` column.
+  ///
+  /// # Arguments
+  /// * `kid` - Identifier of the row the key is stored in.
+  /// * `pem` - PEM private key.
+  pub fn encrypt(&self, kid: &str, pem: &[u8]) -> Fallible<String> {
+    let mut nonce = [0; NONCE_LENGTH];
+    let mut tag   = [0; TAG_LENGTH];
+    rand_bytes(&mut nonce)?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(encrypt_aead(Cipher::aes_256_gcm(), &self.0, Some(&nonce), kid.as_bytes(), pem, &mut tag)?);
+    sealed.extend(&tag);
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, base64::encode(&sealed)))
+  }
+
+  /// Decrypts the `private_key` column of a stored key back into PEM.
+  ///
+  /// # Arguments
+  /// * `kid`       - Identifier of the row the key is stored in.
+  /// * `encrypted` - Value of the `private_key` column.
+  pub fn decrypt(&self, kid: &str, encrypted: &str) -> Fallible<Vec<u8>> {
+    if !encrypted.starts_with(ENCRYPTED_PREFIX) {
+      return Err(format_err!("the private key of signing key {} is not encrypted, store `key encrypt` output instead", kid));
+    }
+
+    let sealed = base64::decode(&encrypted[ENCRYPTED_PREFIX.len()..])?;
+    if sealed.len() < NONCE_LENGTH + TAG_LENGTH {
+      return Err(format_err!("the private key of signing key {} is truncated", kid));
+    }
+
+    let (nonce, rest)     = sealed.split_at(NONCE_LENGTH);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+    decrypt_aead(Cipher::aes_256_gcm(), &self.0, Some(nonce), kid.as_bytes(), ciphertext, tag)
+      .map_err(|_| format_err!("unable to decrypt the private key of signing key {}, check signing.database_key", kid))
+  }
+}
+
+/// A JSON Web Key Set (RFC 7517).
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct JwkSet {
+  pub keys: Vec<Jwk>
+}
+
+/// A public JSON Web Key (RFC 7517).
+/// Published keys may leave out `kid`, `alg` and `use`, they are read as empty strings.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Jwk {
+  pub kty: String,
+
+  #[serde(default)]
+  pub kid: String,
+
+  #[serde(default)]
+  pub alg: String,
+
+  #[serde(rename = "use", default)]
+  pub key_use: String,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub n: Option<String>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub e: Option<String>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub crv: Option<String>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub x: Option<String>,
+
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub y: Option<String>
+}
+
+/// Parses a JWT algorithm name such as `RS256`.
+fn algorithm_named(name: &str) -> Fallible<Algorithm> {
+  serde_json::from_value(serde_json::Value::String(name.to_owned())).map_err(|_| format_err!("unknown algorithm {:?}", name))
+}
+
+/// Curve required by an ECDSA algorithm.
+fn curve(algorithm: Algorithm) -> Nid {
+  if algorithm == Algorithm::ES256 { Nid::X9_62_PRIME256V1 } else { Nid::SECP384R1 }
+}
+
+/// Copies the public half of an EC private key.
+fn public_ec_key(key: &EcKey<Private>) -> Fallible<EcKey<Public>> {
+  Ok(EcKey::from_public_key(key.group(), key.public_key())?)
+}
+
+/// Encodes bytes as unpadded base64url, as used throughout JOSE.
+fn base64_url(bytes: &[u8]) -> String {
+  base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Decodes the base64 body of a PEM document into DER.
+fn pem_to_der(pem: &[u8]) -> Fallible<Vec<u8>> {
+  let body: String = std::str::from_utf8(pem)?
+    .lines()
+    .filter(|line| !line.starts_with("-----"))
+    .collect();
+
+  Ok(base64::decode(&body)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  fn config(algorithm: Algorithm) -> settings::KeyConfig {
+    settings::KeyConfig { algorithm, kid: None, secret: None, private_key: None, public_key: None, retire_at: None }
+  }
+
+  speculate! {
+    it "round trips claims with a shared secret" {
+      let key = SigningKey::from_config(&settings::KeyConfig { secret: Some("kitty".into()), ..config(Algorithm::HS256) }).unwrap();
+      let claims = crate::models::Claims::default();
+
+      let token = key.sign(&claims).unwrap();
+      assert_eq!(key.verify::<crate::models::Claims>(&token).unwrap().claims, claims);
+    }
+
+    it "refuses to load without any key material" {
+      assert!(SigningKey::from_config(&config(Algorithm::HS256)).is_err());
+      assert!(SigningKey::from_config(&config(Algorithm::RS256)).is_err());
+      assert!(SigningKey::from_config(&config(Algorithm::ES256)).is_err());
+    }
+
+    it "selects the verification key by kid" {
+      let old = SigningKey::from_config(&settings::KeyConfig { kid: Some("2019q1".into()), secret: Some("kitty".into()), ..config(Algorithm::HS256) }).unwrap();
+      let new = SigningKey::from_config(&settings::KeyConfig { kid: Some("2019q2".into()), secret: Some("puppy".into()), ..config(Algorithm::HS256) }).unwrap();
+      let claims = crate::models::Claims::default();
+      let token  = old.sign(&claims).unwrap();
+
+      let ring = KeyRing { issuer: DEFAULT_ISSUER.into(), active: new.clone(), previous: vec![old.clone()] };
+      assert_eq!(ring.verify::<crate::models::Claims>(&token).unwrap().claims, claims);
+
+      let retired = SigningKey { retire_at: Some(Utc::now() - chrono::Duration::seconds(1)), ..old };
+      let ring    = KeyRing { issuer: DEFAULT_ISSUER.into(), active: new, previous: vec![retired] };
+      assert!(ring.verify::<crate::models::Claims>(&token).is_err());
+    }
+
+    it "loads keys back from their JWK" {
+      let pem  = Rsa::generate(2048).unwrap().private_key_to_pem().unwrap();
+      let key  = SigningKey::from_pem(Algorithm::RS256, None, Some(&pem), None).unwrap();
+      let jwk  = key.jwk().unwrap();
+      let back = SigningKey::from_jwk(&serde_json::from_str(&serde_json::to_string(&jwk).unwrap()).unwrap()).unwrap();
+      assert_eq!(back.kid, key.kid);
+      assert_eq!(back.jwk(), Some(jwk));
+
+      let token = key.sign(&crate::models::Claims::default()).unwrap();
+      assert!(back.verify::<crate::models::Claims>(&token).is_ok());
+      assert!(back.sign(&crate::models::Claims::default()).is_err());
+    }
+
+    it "never publishes shared secrets" {
+      let key = SigningKey::from_config(&settings::KeyConfig { secret: Some("kitty".into()), ..config(Algorithm::HS256) }).unwrap();
+      assert_eq!(key.jwk(), None);
+      assert_eq!(key.kid, "hmac");
+    }
+
+    it "encrypts stored private keys" {
+      let storage = StorageKey(vec![7; 32]);
+      let pem     = Rsa::generate(2048).unwrap().private_key_to_pem().unwrap();
+      let sealed  = storage.encrypt("2019q3", &pem).unwrap();
+
+      assert!(sealed.starts_with(ENCRYPTED_PREFIX));
+      assert_eq!(storage.decrypt("2019q3", &sealed).unwrap(), pem);
+      assert!(storage.decrypt("2019q4", &sealed).is_err());
+      assert!(StorageKey(vec![8; 32]).decrypt("2019q3", &sealed).is_err());
+      assert!(storage.decrypt("2019q3", std::str::from_utf8(&pem).unwrap()).is_err());
+    }
+
+    it "refuses key rings with duplicate kids" {
+      let secret = |kid: Option<&str>, secret: &str| SigningKey::from_config(&settings::KeyConfig { kid: kid.map(str::to_owned), secret: Some(secret.into()), ..config(Algorithm::HS256) }).unwrap();
+
+      let ring = KeyRing { issuer: DEFAULT_ISSUER.into(), active: secret(None, "puppy"), previous: vec![secret(None, "kitty")] };
+      assert!(ring.check_kids().is_err());
+
+      let ring = KeyRing { issuer: DEFAULT_ISSUER.into(), active: secret(Some("2019q2"), "puppy"), previous: vec![secret(Some("2019q1"), "kitty")] };
+      assert!(ring.check_kids().is_ok());
+
+      let retired = SigningKey { retire_at: Some(Utc::now() - chrono::Duration::seconds(1)), ..secret(None, "kitty") };
+      let ring    = KeyRing { issuer: DEFAULT_ISSUER.into(), active: secret(None, "puppy"), previous: vec![retired] };
+      assert!(ring.check_kids().is_ok());
+    }
+  }
+}
@@ -1,23 +1,98 @@
-use jsonwebtoken::{decode as jwt_decode, Header, Algorithm, Validation};
-use serde::{Deserialize, Serialize};
-use failure::Fallible;
-
-#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
-pub struct Claims {
-  pub sub: String,
-  pub aud: String,
-  pub iat: i64,
-  pub exp: i64,
-  pub nbf: i64
+use openssl::sha::sha256;
+use validator::ValidationError;
+
+use crate::models::Claims;
+use crate::server::HttpError;
+use crate::signing::KeyRing;
+
+/// Prefix of token hashes, naming the hash function.
+const HASH_PREFIX: &str = "sha256:";
+
+/// Number of hex digits of the token hash kept in a fingerprint.
+const FINGERPRINT_DIGITS: usize = 12;
+
+/// Shortest lifetime of an issued token, in seconds (1 minute).
+pub const MIN_TTL: i64 = 60;
+
+/// Longest lifetime of an issued token, in seconds (1 year).
+pub const MAX_TTL: i64 = 31_536_000;
+
+/// Checks the lifetime of a token to issue, whether requested through the API or the CLI.
+///
+/// # Arguments
+/// * `ttl` - Lifetime in seconds.
+pub fn validate_ttl(ttl: i64) -> Result<(), ValidationError> {
+  if ttl < MIN_TTL || ttl > MAX_TTL {
+    let mut error = ValidationError::new("range");
+    error.add_param("min".into(), &MIN_TTL);
+    error.add_param("max".into(), &MAX_TTL);
+    error.add_param("value".into(), &ttl);
+    return Err(error);
+  }
+  Ok(())
+}
+
+/// Verifies the signature of a JWT and decodes its claims.
+///
+/// # Arguments
+/// * `keys`  - Key ring holding the key named by the token's `kid`.
+/// * `token` - Encoded JWT to decode.
+pub fn decode<S>(keys: &KeyRing, token: S) -> Result<Claims, HttpError>
+  where S: AsRef<str> {
+
+  Ok(keys.verify::<Claims>(token.as_ref())?.claims)
+}
+
+/// Hashes a bearer token, for storing it without keeping the token itself.
+///
+/// # Arguments
+/// * `token` - Token to hash.
+pub fn hash<S>(token: S) -> String
+  where S: AsRef<str> {
+
+  let digest: String = sha256(token.as_ref().as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect();
+  format!("{}{}", HASH_PREFIX, digest)
 }
 
-pub fn decode<S>(token: S) -> Fallible<Claims>
-  where S: Into<String> {
+/// A short, non-reversible identifier of a bearer token, safe to log for correlating requests.
+///
+/// # Arguments
+/// * `token` - Token to fingerprint.
+pub fn fingerprint<S>(token: S) -> String
+  where S: AsRef<str> {
 
-  jwt_decode::<Claims>(&token.into(), get_secret().as_ref(), &Validation::default())
-    .map(|data| Ok(data.claims.into()))?
+  let mut hash = hash(token);
+  hash.truncate(HASH_PREFIX.len() + FINGERPRINT_DIGITS);
+  hash
 }
 
-pub fn get_secret() -> String {
-  std::env::var("HEIMDALLR_JWT_SECRET").unwrap_or_else(|_| "supercalifragilisticexpialidocious".into())
+/// Signs claims into an encoded JWT.
+///
+/// # Arguments
+/// * `keys`   - Key ring whose active key signs.
+/// * `claims` - Claims to sign.
+pub fn encode(keys: &KeyRing, claims: &Claims) -> Result<String, HttpError> {
+  keys.sign(claims).map_err(|e| {
+    error!("Unable to sign token: {:?}", e);
+    HttpError::InternalServerError
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use speculate::speculate;
+  use super::*;
+
+  speculate! {
+    it "hashes tokens" {
+      assert_eq!(hash("kitty"), "sha256:67731ff58137eb39713ae30eba33c54c8c1d5418e081428ca815e4e733d64f6d");
+    }
+
+    it "fingerprints tokens without exposing them" {
+      let fingerprint = fingerprint("kitty");
+      assert_eq!(fingerprint, "sha256:67731ff58137");
+      assert_eq!(fingerprint, super::fingerprint(String::from("kitty")));
+      assert!(!fingerprint.contains("kitty"));
+    }
+  }
 }